@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    app,
+    decrypto::Team,
+    id::{GameId, UserId},
+    metrics,
+};
+
+/// Routes for server operators, mounted under `/admin`.
+/// Every request needs an `Authorization: Bearer <token>` header with the configured `admin_token`.
+pub fn router(token: String) -> Router<Arc<Mutex<app::State>>> {
+    Router::new()
+        .route("/games", get(get_games))
+        .route("/games/{game_id}", get(get_game).delete(delete_game))
+        .route("/games/{game_id}/end", post(post_end_game))
+        .route("/users", get(get_users))
+        .route("/users/{user_id}/kick", post(post_kick))
+        .route("/users/{user_id}/ban", post(post_ban).delete(delete_ban))
+        .route("/broadcast", post(post_broadcast))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(token),
+            require_token,
+        ))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GameSummary {
+    pub id: GameId,
+    pub phase: GamePhase,
+    pub players: Vec<PlayerSummary>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamePhase {
+    Lobby,
+    InGame,
+    GameOver,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PlayerSummary {
+    pub id: UserId,
+    pub nick: Option<String>,
+    /// Team the player is or was in.
+    pub team: Option<Team>,
+    pub is_in_game: bool,
+    pub connected: bool,
+    pub is_bot: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UserSummary {
+    pub id: UserId,
+    pub nick: Option<String>,
+    pub game: Option<GameId>,
+    pub connected: bool,
+    /// Devices the user is connected from, see [`app::device_name`].
+    pub devices: Vec<String>,
+    pub is_bot: bool,
+    pub banned: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BroadcastRequest {
+    text: String,
+}
+
+async fn require_token(
+    State(token): State<Arc<String>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Response {
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    match given {
+        Some(given) if tokens_match(given, &token) => next.run(request).await,
+        Some(_) => {
+            tracing::warn!(
+                "Rejected admin request to {} with a wrong token",
+                request.uri()
+            );
+            (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response()
+        }
+        None => (StatusCode::UNAUTHORIZED, "Credentials required").into_response(),
+    }
+}
+
+/// Compares in constant time, so the token cannot be guessed byte by byte from response times.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    let (given, expected) = (given.as_bytes(), expected.as_bytes());
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Result of an action on an existing game or user.
+fn action_response(result: Result<(), String>) -> Response {
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err).into_response(),
+    }
+}
+
+fn game_not_found(game_id: GameId) -> Response {
+    tracing::warn!("Game {game_id:?} not found");
+    (StatusCode::NOT_FOUND, "Game not found").into_response()
+}
+
+fn user_not_found(user_id: UserId) -> Response {
+    tracing::warn!("User {user_id:?} not found");
+    (StatusCode::NOT_FOUND, "User not found").into_response()
+}
+
+/// All games with their state and players.
+async fn get_games(State(state): State<Arc<Mutex<app::State>>>) -> Response {
+    Json(metrics::lock(&state).await.game_summaries()).into_response()
+}
+
+/// Full state of a game, including the keywords of both teams.
+async fn get_game(
+    Path(game_id): Path<GameId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    match metrics::lock(&state).await.games.get(&game_id) {
+        Some(game_info) => Json(game_info).into_response(),
+        None => game_not_found(game_id),
+    }
+}
+
+/// End a game in progress without a winner.
+async fn post_end_game(
+    Path(game_id): Path<GameId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let mut state = metrics::lock(&state).await;
+    if !state.games.contains_key(&game_id) {
+        return game_not_found(game_id);
+    }
+    tracing::info!("Admin ended game {game_id:?}");
+    action_response(state.force_end_game(game_id).await)
+}
+
+/// Remove a game in any state.
+async fn delete_game(
+    Path(game_id): Path<GameId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let mut state = metrics::lock(&state).await;
+    if !state.games.contains_key(&game_id) {
+        return game_not_found(game_id);
+    }
+    tracing::info!("Admin deleted game {game_id:?}");
+    action_response(state.delete_game(game_id).await)
+}
+
+/// Users that are connected, in a game or banned.
+async fn get_users(State(state): State<Arc<Mutex<app::State>>>) -> Response {
+    Json(metrics::lock(&state).await.user_summaries()).into_response()
+}
+
+async fn post_kick(
+    Path(user_id): Path<UserId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let mut state = metrics::lock(&state).await;
+    if !state.user_exists(user_id) {
+        return user_not_found(user_id);
+    }
+    tracing::info!("Admin kicked user {user_id:?}");
+    action_response(state.kick_user(user_id).await)
+}
+
+async fn post_ban(
+    Path(user_id): Path<UserId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    set_banned(state, user_id, true).await
+}
+
+async fn delete_ban(
+    Path(user_id): Path<UserId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    set_banned(state, user_id, false).await
+}
+
+async fn set_banned(state: Arc<Mutex<app::State>>, user_id: UserId, banned: bool) -> Response {
+    let mut state = metrics::lock(&state).await;
+    if !state.user_exists(user_id) {
+        return user_not_found(user_id);
+    }
+    tracing::info!(
+        "Admin {} user {user_id:?}",
+        if banned { "banned" } else { "unbanned" }
+    );
+    action_response(state.set_banned(user_id, banned).await)
+}
+
+/// Post a server message to the chat of every game, for example before a restart.
+/// Responds with the number of games that got it.
+async fn post_broadcast(
+    State(state): State<Arc<Mutex<app::State>>>,
+    Json(request): Json<BroadcastRequest>,
+) -> Response {
+    match metrics::lock(&state)
+        .await
+        .broadcast_chat(request.text)
+        .await
+    {
+        Ok(count) => (StatusCode::OK, count.to_string()).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+}
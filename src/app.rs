@@ -1,52 +1,226 @@
-use std::{collections::HashMap, fmt::format, hash::Hash, option, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::format,
+    hash::Hash,
+    option,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
-use axum::extract::ws::WebSocket;
+use axum::extract::ws::{CloseFrame, WebSocket, close_code};
 use futures::{SinkExt, stream::SplitSink};
 use rand::seq::IndexedRandom;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, mpsc};
+use tracing::Instrument;
 
 use crate::{
+    admin::{GamePhase, GameSummary, PlayerSummary, UserSummary},
+    archive::Archive,
+    bot::{BotKind, NewBot, model::AssociationModel},
+    config::Config,
     decrypto::{
         GameInfo, GameInfoState, GameInfoStateCurrentRound, GamePlayerInfo, GameState, PerTeam,
-        Team, check_tiebreaker_guess, settings::GameSettings,
+        Team, check_tiebreaker_guess,
+        record::GameRecord,
+        registry::WordlistRegistry,
+        settings::{ClueMode, GameSettings},
+        wordlist::{Wordlist, custom_wordlist_from_words},
+    },
+    drawing::{
+        DrawingData, DrawingFormat, check_clue_format, check_drawing_owned,
+        collect_unused_drawings, drawing_data_url,
     },
-    id::{ConnectionId, GameId, UserId, UserSecret},
+    id::{ConnectionId, DrawingId, GameId, UserId, UserSecret},
     message::{
         ChatMessage, Clue, CompletedRoundPerTeam, CurrentRoundPerTeam, Deadline, DeadlineReason,
-        ErrorSeverity, FromClient, GameStateView, GameView, Inputs, PlayerInfo,
+        DeviceInfo, ErrorSeverity, FromClient, GameStateView, GameView, Inputs, PlayerInfo,
         TiebreakerInputSubmission, ToClient, UserInfo,
     },
+    metrics::{self, GameOutcome, METRICS, Metrics},
+    pairing::PairingCodes,
+    rate_limit::{RateLimiter, Verdict},
+    suggest::{clue_history, rank_codes, slot_scores},
+    users::StoredUser,
 };
 
-#[derive(Default)]
+/// Codes suggested when the client doesn't ask for a number, and the most it can ask for.
+const DEFAULT_SUGGESTIONS: usize = 5;
+const MAX_SUGGESTIONS: usize = 20;
+
 pub struct State {
     clients: HashMap<ConnectionId, ClientData>,
     users: HashMap<UserId, UserData>,
     pub games: HashMap<GameId, GameInfo>,
+    /// Replaced by a reloaded copy when the wordlist files change.
+    pub wordlists: WordlistRegistry,
+    /// Finished games, kept after their lobby is gone.
+    pub archive: Archive,
+    pub config: Arc<Config>,
+    /// Word associations for bots and code suggestions, if configured.
+    model: Option<Arc<AssociationModel>>,
+    /// Hands new bots over to be played. `None` if no kind of bot is configured.
+    bots: Option<mpsc::UnboundedSender<NewBot>>,
+    /// Codes for logging in as an existing user on another device.
+    pairing_codes: PairingCodes,
+    /// Users changed since they were last saved, see [`State::take_users_to_save`].
+    users_changed: bool,
+    /// The state itself, for work that is finished off the lock.
+    shared: Weak<Mutex<State>>,
+}
+
+/// Record of a game whose drawings are still to be read, see [`State::export_game`].
+pub struct PendingRecord {
+    record: GameRecord,
+    drawings: Vec<(DrawingId, DrawingFormat, DrawingData)>,
+}
+
+impl PendingRecord {
+    /// Reads the drawings and embeds them in the record.
+    pub async fn load(self) -> GameRecord {
+        let mut record = self.record;
+        for (drawing_id, format, data) in self.drawings {
+            let url = match data.load().await {
+                Ok(bytes) => drawing_data_url(format, &bytes),
+                Err(err) => Err(err.to_string()),
+            };
+            match url {
+                Ok(url) => {
+                    record.drawings.insert(drawing_id, url);
+                }
+                Err(err) => tracing::error!("Cannot embed drawing {drawing_id:?}: {err}"),
+            }
+        }
+        record
+    }
 }
 
 pub struct ClientData {
-    outbound: SplitSink<WebSocket, axum::extract::ws::Message>,
+    outbound: Outbound,
     authenticated_as: Option<UserId>,
+    /// Description of the device, see [`device_name`].
+    device: String,
+    connected_at: Instant,
+}
+
+pub enum Outbound {
+    WebSocket(SplitSink<WebSocket, axum::extract::ws::Message>),
+    /// Messages to a bot, played in the same process.
+    Bot(mpsc::UnboundedSender<ToClient>),
 }
 
 pub struct UserData {
-    /// Null if not connected at the moment.
-    connection_id: Option<ConnectionId>,
+    /// Open connections, as the same user can play from several devices. Empty if not connected.
+    connections: HashSet<ConnectionId>,
     /// Secret used to authenticate the user.
     secret: UserSecret,
     /// User-given nickname.
     nick: Option<String>,
     /// Joined game, if any.
     game: Option<GameId>,
+    /// Kind of the bot, `None` for humans.
+    bot: Option<BotKind>,
+    /// Set by an administrator, prevents logging in.
+    banned: bool,
+    /// Limits on what the user sends, over all their connections.
+    rate_limiter: RateLimiter,
+}
+
+impl UserData {
+    fn is_connected(&self) -> bool {
+        !self.connections.is_empty()
+    }
+}
+
+/// Short description of a device, such as `Firefox on Android`, from its `User-Agent` header.
+pub fn device_name(user_agent: Option<&str>) -> String {
+    // Checked in order, as for example Chrome also claims to be Safari, and Android to be Linux.
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ];
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Android", "Android"),
+        ("iPhone", "iPhone"),
+        ("iPad", "iPad"),
+        ("Windows", "Windows"),
+        ("CrOS", "ChromeOS"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ];
+    let find = |names: &[(&str, &'static str)]| {
+        let user_agent = user_agent?;
+        names
+            .iter()
+            .find(|(token, _)| user_agent.contains(token))
+            .map(|(_, name)| *name)
+    };
+    match (find(BROWSERS), find(SYSTEMS)) {
+        (Some(browser), Some(system)) => format!("{browser} on {system}"),
+        (Some(name), None) | (None, Some(name)) => name.to_owned(),
+        (None, None) => "Unknown device".to_owned(),
+    }
 }
 
 impl State {
+    pub fn new(
+        config: Arc<Config>,
+        wordlists: WordlistRegistry,
+        archive: Archive,
+        users: Vec<StoredUser>,
+        model: Option<Arc<AssociationModel>>,
+        bots: Option<mpsc::UnboundedSender<NewBot>>,
+        shared: Weak<Mutex<State>>,
+    ) -> Self {
+        let users = users
+            .into_iter()
+            .map(|user| {
+                let data = UserData {
+                    connections: HashSet::new(),
+                    secret: user.secret,
+                    nick: user.nick,
+                    game: None,
+                    bot: None,
+                    banned: user.banned,
+                    rate_limiter: RateLimiter::default(),
+                };
+                (user.id, data)
+            })
+            .collect();
+        Self {
+            clients: HashMap::new(),
+            users,
+            games: HashMap::new(),
+            wordlists,
+            archive,
+            config,
+            model,
+            bots,
+            pairing_codes: PairingCodes::default(),
+            users_changed: false,
+            shared,
+        }
+    }
+
     pub async fn send_to_connection(&mut self, id: ConnectionId, msg: ToClient) {
-        log::debug!("Sending message to client {id:?}: {:?}", msg);
-        let msg = axum::extract::ws::Message::Text(serde_json::to_string(&msg).unwrap().into());
+        tracing::trace!(connection_id = %id, kind = msg.kind(), "Sending message");
+        if let ToClient::Error { message, .. } = &msg {
+            METRICS.error_sent(message);
+        }
         let client = self.clients.get_mut(&id).expect("Client should exist");
-        let _ = client.outbound.send(msg).await;
+        match &mut client.outbound {
+            Outbound::WebSocket(sink) => {
+                let msg =
+                    axum::extract::ws::Message::Text(serde_json::to_string(&msg).unwrap().into());
+                let _ = sink.send(msg).await;
+            }
+            Outbound::Bot(sender) => {
+                let _ = sender.send(msg);
+            }
+        }
     }
 
     pub async fn send_error<S>(&mut self, id: ConnectionId, msg: S, severity: ErrorSeverity)
@@ -98,12 +272,15 @@ impl State {
         &mut self,
         id: ConnectionId,
         outbound: SplitSink<WebSocket, axum::extract::ws::Message>,
+        device: String,
     ) {
         self.clients.insert(
             id,
             ClientData {
-                outbound,
+                outbound: Outbound::WebSocket(outbound),
                 authenticated_as: None,
+                device,
+                connected_at: Instant::now(),
             },
         );
     }
@@ -111,15 +288,145 @@ impl State {
     pub async fn on_disconnect(&mut self, id: ConnectionId) {
         let client = self.clients.remove(&id).expect("Should exist");
         if let Some(user_id) = client.authenticated_as {
-            let user_data = self.users.get_mut(&user_id).expect("Should exist");
-            user_data.connection_id = None;
-            if let Some(game_id) = user_data.game {
-                self.broadcast_game_state(game_id).await;
-            }
+            self.detach_connection(user_id, id).await;
+        }
+    }
+
+    /// Remove a connection from a user, and tell everyone who can see the user.
+    async fn detach_connection(&mut self, user_id: UserId, connection_id: ConnectionId) {
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        user_data.connections.remove(&connection_id);
+        if let Some(game_id) = user_data.game {
+            self.broadcast_game_state(game_id).await;
+        } else if self.is_disposable(user_id) {
+            // Nothing to come back to, so don't keep every anonymous visitor around forever.
+            self.users.remove(&user_id);
+            self.pairing_codes.revoke(user_id);
+        } else {
+            // The other devices list the ones still connected.
+            self.send_state_to_user(user_id).await;
+        }
+    }
+
+    /// Whether the user is worth keeping across restarts: they have a nick or played games.
+    fn is_worth_saving(&self, user_id: UserId) -> bool {
+        let user_data = &self.users[&user_id];
+        user_data.bot.is_none()
+            && (user_data.nick.is_some() || user_data.banned || self.archive.has_played(user_id))
+    }
+
+    /// Whether the user can be forgotten: disconnected, not worth saving and not in any game.
+    fn is_disposable(&self, user_id: UserId) -> bool {
+        let user_data = &self.users[&user_id];
+        !user_data.is_connected()
+            && user_data.bot.is_none()
+            && user_data.game.is_none()
+            && !self.is_worth_saving(user_id)
+            && !self
+                .games
+                .values()
+                .any(|game_info| game_info.players().contains_key(&user_id))
+    }
+
+    /// Log a connection in as a user, logging it out of any other user first.
+    async fn attach_connection(&mut self, id: ConnectionId, user_id: UserId) {
+        let previous = self
+            .clients
+            .get_mut(&id)
+            .expect("Should exist")
+            .authenticated_as
+            .replace(user_id);
+        if let Some(previous) = previous
+            && previous != user_id
+        {
+            self.detach_connection(previous, id).await;
+        }
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        user_data.connections.insert(id);
+        tracing::debug!(%user_id, devices = user_data.connections.len(), "Connection attached");
+
+        // Others in the game see the user as connected, the user's other devices see this one.
+        match user_data.game {
+            Some(game_id) => self.broadcast_game_state(game_id).await,
+            None => self.send_state_to_user(user_id).await,
+        }
+    }
+
+    /// Log out every connection of a user except `keep`, and close them.
+    async fn revoke_connections(&mut self, user_id: UserId, keep: ConnectionId) {
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        let revoked: Vec<_> = user_data
+            .connections
+            .iter()
+            .copied()
+            .filter(|connection_id| *connection_id != keep)
+            .collect();
+        for connection_id in revoked {
+            // Detached right away, so that they get no more state updates with the new secret.
+            self.users
+                .get_mut(&user_id)
+                .expect("Should exist")
+                .connections
+                .remove(&connection_id);
+            self.clients
+                .get_mut(&connection_id)
+                .expect("Should exist")
+                .authenticated_as = None;
+            self.send_error(
+                connection_id,
+                "Your session was moved to another device",
+                ErrorSeverity::Warning,
+            )
+            .await;
+            let frame = CloseFrame {
+                code: close_code::NORMAL,
+                reason: "Your session was moved to another device".into(),
+            };
+            self.close_connection(connection_id, Some(frame)).await;
+        }
+    }
+
+    /// Descriptions of the devices a user is connected from, oldest connection first.
+    fn devices(&self, user_id: UserId) -> Vec<(ConnectionId, &str)> {
+        let mut clients: Vec<_> = self.users[&user_id]
+            .connections
+            .iter()
+            .map(|id| (*id, &self.clients[id]))
+            .collect();
+        clients.sort_by_key(|(_, client)| client.connected_at);
+        clients
+            .into_iter()
+            .map(|(id, client)| (id, client.device.as_str()))
+            .collect()
+    }
+
+    /// Mark the users to be saved, so they can reconnect after a restart.
+    /// They are written in batches by the caller of [`State::take_users_to_save`].
+    pub fn persist_users(&mut self) {
+        self.users_changed = true;
+    }
+
+    /// Users to save if anything changed since the last call, see [`Self::is_worth_saving`].
+    pub fn take_users_to_save(&mut self) -> Option<Vec<StoredUser>> {
+        if !std::mem::take(&mut self.users_changed) {
+            return None;
         }
+        let mut users: Vec<_> = self
+            .users
+            .iter()
+            .filter(|(id, _)| self.is_worth_saving(**id))
+            .map(|(id, user)| StoredUser {
+                id: *id,
+                secret: user.secret,
+                nick: user.nick.clone(),
+                banned: user.banned,
+            })
+            .collect();
+        users.sort_by_key(|user| user.id);
+        Some(users)
     }
 
-    fn find_client_by_secret(&mut self, secret: UserSecret) -> Option<UserId> {
+    pub fn find_client_by_secret(&self, secret: UserSecret) -> Option<UserId> {
         for (user_id, user_data) in &self.users {
             if user_data.secret == secret {
                 debug_assert!(self.users.contains_key(user_id));
@@ -129,7 +436,28 @@ impl State {
         None
     }
 
-    fn game_from_user_perspective(&self, user_id: UserId) -> Option<GameView> {
+    /// Split of the lobby's players into teams of close ratings, the same for every player.
+    /// Computed once per broadcast rather than per view.
+    fn suggested_teams(&self, game_id: GameId) -> Option<PerTeam<Vec<UserId>>> {
+        let game_info = self.games.get(&game_id).expect("Should exist");
+        if !matches!(game_info.state, GameInfoState::Lobby) {
+            return None;
+        }
+        let mut players: Vec<_> = game_info
+            .players()
+            .iter()
+            .filter(|(_, info)| !matches!(info, GamePlayerInfo::LeftGame(_)))
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        players.sort();
+        self.archive.ratings().balanced_teams(&players)
+    }
+
+    fn game_from_user_perspective(
+        &self,
+        user_id: UserId,
+        suggested_teams: Option<&PerTeam<Vec<UserId>>>,
+    ) -> Option<GameView> {
         let user_data = self.users.get(&user_id).expect("Should exist");
 
         user_data.game.map(|game_id| {
@@ -142,13 +470,16 @@ impl State {
                     let player_info = self.users.get(player_id).expect("Should exist");
                     PlayerInfo {
                         id: *player_id,
-                        connected: player_info.connection_id.is_some(),
+                        connected: player_info.is_connected(),
+                        devices: player_info.connections.len(),
                         nick: player_info.nick.clone().expect("Should exist"),
                         team: match info {
                             GamePlayerInfo::InTeam(team) => Some(*team),
                             _ => None,
                         },
                         is_in_game: !matches!(info, GamePlayerInfo::LeftGame(_)),
+                        is_bot: player_info.bot.is_some(),
+                        rating: self.archive.ratings().rating(*player_id),
                     }
                 })
                 .collect();
@@ -156,7 +487,14 @@ impl State {
 
             let state = match &game_info.state {
                 GameInfoState::Lobby => GameStateView::Lobby {
-                    reason_not_startable: game_info.startable().err().map(|e| e.to_owned()),
+                    reason_not_startable: game_info
+                        .startable(&self.wordlists)
+                        .err()
+                        .map(|e| e.to_owned()),
+                    keyword_history: game_info.keyword_history.clone(),
+                    association_bots_available: self.config.bot_model.is_some(),
+                    llm_bots_available: self.config.llm.is_some(),
+                    suggested_teams: suggested_teams.cloned(),
                 },
                 GameInfoState::InGame {
                     keywords,
@@ -257,6 +595,7 @@ impl State {
                                                             TiebreakerInputSubmission::new(
                                                                 guess,
                                                                 &keywords[team.other()][i],
+                                                                &game_info.language,
                                                             )
                                                         })
                                                     })
@@ -291,6 +630,7 @@ impl State {
                         })
                         .collect(),
                     tiebreaker: tiebreaker.clone(),
+                    record_id: game_info.record_id,
                 },
             };
 
@@ -300,26 +640,68 @@ impl State {
                 players,
                 state,
                 settings: game_info.settings.clone(),
+                custom_wordlist: game_info.custom_wordlist.as_ref().map(|w| w.info.clone()),
             }
         })
     }
 
-    /// Gather latest state view and send it to the user.
+    /// Gather latest state view and send it to every connection of the user.
     async fn send_state_to_user(&mut self, user_id: UserId) {
-        let user_data = self.users.get(&user_id).expect("Should exist");
+        let suggested_teams = self.users[&user_id]
+            .game
+            .and_then(|game_id| self.suggested_teams(game_id));
+        self.send_state_with_teams(user_id, suggested_teams.as_ref())
+            .await;
+    }
 
-        let game = self.game_from_user_perspective(user_id);
+    /// [`Self::send_state_to_user`], with the [`Self::suggested_teams`] of their game.
+    async fn send_state_with_teams(
+        &mut self,
+        user_id: UserId,
+        suggested_teams: Option<&PerTeam<Vec<UserId>>>,
+    ) {
+        let user_data = self.users.get(&user_id).expect("Should exist");
+        if !user_data.is_connected() {
+            return;
+        }
 
-        let state = ToClient::State {
-            user_info: UserInfo {
-                id: user_id,
-                secret: user_data.secret,
-                nick: user_data.nick.clone(),
-            },
-            game,
+        let game = self.game_from_user_perspective(user_id, suggested_teams);
+        let user_info = UserInfo {
+            id: user_id,
+            secret: user_data.secret,
+            nick: user_data.nick.clone(),
+            stats: self.archive.stats(user_id),
+            devices: Vec::new(),
         };
-        self.send_to_connection(user_data.connection_id.expect("Should exist"), state)
-            .await;
+        let devices: Vec<_> = self
+            .devices(user_id)
+            .into_iter()
+            .map(|(id, name)| (id, name.to_owned()))
+            .collect();
+
+        for (connection_id, _) in &devices {
+            let mut user_info = user_info.clone();
+            user_info.devices = devices
+                .iter()
+                .map(|(id, name)| DeviceInfo {
+                    name: name.clone(),
+                    current: id == connection_id,
+                })
+                .collect();
+            let state = ToClient::State {
+                user_info,
+                game: game.clone(),
+            };
+            self.send_to_connection(*connection_id, state).await;
+        }
+    }
+
+    /// Send an error to every connection of the user.
+    async fn send_error_to_user(&mut self, user_id: UserId, msg: &str, severity: ErrorSeverity) {
+        let connections: Vec<_> = self.users[&user_id].connections.iter().copied().collect();
+        for connection_id in connections {
+            self.send_error(connection_id, msg, severity.clone()).await;
+        }
     }
 
     /// Broadcast the current game state to all users in the game.
@@ -333,44 +715,544 @@ impl State {
             .keys()
             .copied()
             .collect();
+        let suggested_teams = self.suggested_teams(game_id);
         let mut any_user_connected = false;
         for user_id in &users_in_game {
-            if self
-                .users
-                .get(user_id)
-                .expect("Should exist")
-                .connection_id
-                .is_none()
-            {
+            let user_data = self.users.get(user_id).expect("Should exist");
+            if !user_data.is_connected() {
                 continue; // Don't send state to users that are not connected.
             }
-            self.send_state_to_user(*user_id).await;
-            any_user_connected = true;
+            // Bots alone don't keep the game alive.
+            any_user_connected |= user_data.bot.is_none();
+            self.send_state_with_teams(*user_id, suggested_teams.as_ref())
+                .await;
         }
         if !any_user_connected {
             // If no user is connected, remove the game.
-            log::debug!("Removing game {game_id:?} because no users are connected");
+            tracing::info!(%game_id, "Removing game, no users are connected");
             for user_id in users_in_game {
                 let user_data = self.users.get_mut(&user_id).expect("Should exist");
                 user_data.game = None; // Clear game reference for users.
+                if user_data.bot.is_some() {
+                    self.remove_bot(user_id);
+                }
             }
             self.games.remove(&game_id);
         }
     }
 
+    /// Disconnect a bot, which stops it from playing.
+    fn remove_bot(&mut self, user_id: UserId) {
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        for connection_id in user_data.connections.drain() {
+            // Dropping the sender ends the bot's task.
+            self.clients.remove(&connection_id);
+        }
+    }
+
+    /// Close all connections of a user, which logs them out. Bots are removed instead.
+    async fn disconnect_user(&mut self, user_id: UserId) {
+        let user_data = self.users.get(&user_id).expect("Should exist");
+        if user_data.bot.is_some() {
+            self.remove_bot(user_id);
+            return;
+        }
+        let connections: Vec<_> = user_data.connections.iter().copied().collect();
+        for connection_id in connections {
+            self.close_connection(connection_id, None).await;
+        }
+    }
+
+    /// Check that a websocket client is still there. Browsers answer with a pong on their own.
+    pub async fn send_ping(&mut self, connection_id: ConnectionId) {
+        if let Some(ClientData {
+            outbound: Outbound::WebSocket(sink),
+            ..
+        }) = self.clients.get_mut(&connection_id)
+        {
+            let _ = sink
+                .send(axum::extract::ws::Message::Ping(Default::default()))
+                .await;
+        }
+    }
+
+    /// Ask a websocket client to close the connection.
+    async fn close_connection(&mut self, connection_id: ConnectionId, frame: Option<CloseFrame>) {
+        if let Some(ClientData {
+            outbound: Outbound::WebSocket(sink),
+            ..
+        }) = self.clients.get_mut(&connection_id)
+        {
+            // The socket task cleans up once the client acknowledges the close.
+            let _ = sink.send(axum::extract::ws::Message::Close(frame)).await;
+        }
+    }
+
+    /// Tell every websocket client that the server is going away, and ask them to close the connection.
+    pub async fn notify_shutdown(&mut self, expected_downtime_secs: Option<u64>) {
+        let connection_ids: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| matches!(client.outbound, Outbound::WebSocket(_)))
+            .map(|(id, _)| *id)
+            .collect();
+        for connection_id in connection_ids {
+            self.send_to_connection(
+                connection_id,
+                ToClient::ServerRestarting {
+                    expected_downtime_secs,
+                },
+            )
+            .await;
+            let frame = CloseFrame {
+                code: close_code::RESTART,
+                reason: "Server restarting".into(),
+            };
+            self.close_connection(connection_id, Some(frame)).await;
+        }
+    }
+
+    /// Number of open websocket connections, bots excluded.
+    pub fn websocket_count(&self) -> usize {
+        self.clients
+            .values()
+            .filter(|client| matches!(client.outbound, Outbound::WebSocket(_)))
+            .count()
+    }
+
+    pub fn user_exists(&self, user_id: UserId) -> bool {
+        self.users.contains_key(&user_id)
+    }
+
+    /// All games, for the admin API.
+    pub fn game_summaries(&self) -> Vec<GameSummary> {
+        let mut games: Vec<_> = self
+            .games
+            .iter()
+            .map(|(game_id, game_info)| {
+                let mut players: Vec<_> = game_info
+                    .players()
+                    .iter()
+                    .map(|(player_id, info)| {
+                        let user_data = self.users.get(player_id).expect("Should exist");
+                        PlayerSummary {
+                            id: *player_id,
+                            nick: user_data.nick.clone(),
+                            team: info.access_to_info(),
+                            is_in_game: !matches!(info, GamePlayerInfo::LeftGame(_)),
+                            connected: user_data.is_connected(),
+                            is_bot: user_data.bot.is_some(),
+                        }
+                    })
+                    .collect();
+                players.sort_by_key(|p| p.id);
+                GameSummary {
+                    id: *game_id,
+                    phase: match game_info.state {
+                        GameInfoState::Lobby => GamePhase::Lobby,
+                        GameInfoState::InGame { .. } => GamePhase::InGame,
+                        GameInfoState::GameOver { .. } => GamePhase::GameOver,
+                    },
+                    players,
+                }
+            })
+            .collect();
+        games.sort_by_key(|g| g.id);
+        games
+    }
+
+    /// Users that are connected, in a game or banned, for the admin API.
+    pub fn user_summaries(&self) -> Vec<UserSummary> {
+        let mut users: Vec<_> = self
+            .users
+            .iter()
+            .filter(|(_, user)| user.is_connected() || user.game.is_some() || user.banned)
+            .map(|(user_id, user)| UserSummary {
+                id: *user_id,
+                nick: user.nick.clone(),
+                game: user.game,
+                connected: user.is_connected(),
+                devices: self
+                    .devices(*user_id)
+                    .into_iter()
+                    .map(|(_, name)| name.to_owned())
+                    .collect(),
+                is_bot: user.bot.is_some(),
+                banned: user.banned,
+            })
+            .collect();
+        users.sort_by_key(|u| u.id);
+        users
+    }
+
+    /// End a game in progress without a winner, see [`GameInfo::force_end`].
+    pub async fn force_end_game(&mut self, game_id: GameId) -> Result<(), String> {
+        let Some(game_info) = self.games.get_mut(&game_id) else {
+            return Err("Game not found".to_owned());
+        };
+        game_info.force_end()?;
+        tracing::info!(%game_id, "Game ended by an administrator");
+        METRICS.game_finished(GameOutcome::Aborted, game_info.started_at);
+        game_info.global_chat.push(ChatMessage::system(
+            "The game was ended by an administrator".to_owned(),
+        ));
+        self.broadcast_game_state(game_id).await;
+        Ok(())
+    }
+
+    /// Remove a game, sending its players back to the start page.
+    pub async fn delete_game(&mut self, game_id: GameId) -> Result<(), String> {
+        let Some(game_info) = self.games.remove(&game_id) else {
+            return Err("Game not found".to_owned());
+        };
+        for user_id in game_info.players().keys() {
+            let user_data = self.users.get_mut(user_id).expect("Should exist");
+            if user_data.game != Some(game_id) {
+                continue;
+            }
+            user_data.game = None;
+            if user_data.bot.is_some() {
+                self.remove_bot(*user_id);
+            } else {
+                self.send_error_to_user(
+                    *user_id,
+                    "The game was closed by an administrator",
+                    ErrorSeverity::Warning,
+                )
+                .await;
+                self.send_state_to_user(*user_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a user from their game and close their connection.
+    /// They can log in again unless they are banned.
+    pub async fn kick_user(&mut self, user_id: UserId) -> Result<(), String> {
+        let Some(user_data) = self.users.get_mut(&user_id) else {
+            return Err("User not found".to_owned());
+        };
+        let game_id = user_data.game.take();
+
+        self.disconnect_user(user_id).await;
+        if let Some(game_id) = game_id {
+            let game_info = self.games.get_mut(&game_id).expect("Should exist");
+            game_info.kick_player(user_id);
+            game_info.global_chat.push(ChatMessage::system(format!(
+                "<{user_id}> was removed from the game by an administrator"
+            )));
+            self.broadcast_game_state(game_id).await;
+        }
+        Ok(())
+    }
+
+    /// Ban or unban a user. Banning also kicks them.
+    pub async fn set_banned(&mut self, user_id: UserId, banned: bool) -> Result<(), String> {
+        let Some(user_data) = self.users.get_mut(&user_id) else {
+            return Err("User not found".to_owned());
+        };
+        if user_data.bot.is_some() {
+            return Err("Bots cannot be banned".to_owned());
+        }
+        user_data.banned = banned;
+        self.persist_users();
+        if banned {
+            self.pairing_codes.revoke(user_id);
+            self.kick_user(user_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Post a server message to the chat of every game. Returns the number of games.
+    pub async fn broadcast_chat(&mut self, text: String) -> Result<usize, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err("Message is empty".to_owned());
+        }
+        if text.len() > self.config.limits.max_chat_message_bytes {
+            return Err("Message too long".to_owned());
+        }
+
+        let game_ids: Vec<_> = self.games.keys().copied().collect();
+        for game_id in &game_ids {
+            let game_info = self.games.get_mut(game_id).expect("Should exist");
+            game_info
+                .global_chat
+                .push(ChatMessage::system(text.to_owned()));
+            self.broadcast_game_state(*game_id).await;
+        }
+        Ok(game_ids.len())
+    }
+
+    /// Record of a finished game. The drawings used as clues are embedded by
+    /// [`PendingRecord::load`], which reads them and should be called off the lock.
+    pub fn export_game(&self, game_id: GameId) -> Result<PendingRecord, String> {
+        let Some(game_info) = self.games.get(&game_id) else {
+            return Err("Game not found".to_owned());
+        };
+
+        let record = GameRecord::new(
+            game_id,
+            game_info,
+            |user_id| {
+                self.users
+                    .get(&user_id)
+                    .and_then(|user| user.nick.clone())
+                    .unwrap_or_default()
+            },
+            |user_id| {
+                self.users
+                    .get(&user_id)
+                    .is_some_and(|user| user.bot.is_some())
+            },
+        )?;
+        let drawings = record
+            .drawing_ids()
+            .into_iter()
+            .filter_map(|drawing_id| {
+                let drawing = game_info.drawings.get(&drawing_id)?;
+                Some((drawing_id, drawing.format, drawing.data.clone()))
+            })
+            .collect();
+        Ok(PendingRecord { record, drawings })
+    }
+
+    /// Archive the game if it has just finished, so it can be looked up after the lobby is gone.
+    /// The record is written by a task, as it reads the drawings and writes files.
+    fn archive_if_finished(&mut self, game_id: GameId) {
+        let game_info = self.games.get(&game_id).expect("Should exist");
+        let GameInfoState::GameOver { winner, .. } = game_info.state else {
+            return;
+        };
+        if game_info.record_id.is_some() || game_info.archiving.is_some() {
+            return;
+        }
+        tracing::info!(%game_id, winner = winner.map(Team::index), "Game over");
+        let outcome = winner.map_or(GameOutcome::Draw, GameOutcome::Won);
+        METRICS.game_finished(outcome, game_info.started_at);
+
+        let pending = match self.export_game(game_id) {
+            Ok(pending) => pending,
+            Err(err) => {
+                tracing::error!("Cannot record game {game_id:?}: {err}");
+                return;
+            }
+        };
+        let record_id = pending.record.id;
+        let game_info = self.games.get_mut(&game_id).expect("Should exist");
+        game_info.archiving = Some(record_id);
+
+        let dir = self.archive.dir().to_owned();
+        let shared = self.shared.clone();
+        let task = async move {
+            let record = pending.load().await;
+            let written = Archive::write(&dir, &record).await;
+            let Some(state) = shared.upgrade() else {
+                return;
+            };
+            let mut state = metrics::lock(&state).await;
+            match &written {
+                Ok(()) => state.archive.add(&record),
+                Err(err) => tracing::error!("Cannot archive game {game_id:?}: {err}"),
+            }
+            // The lobby may be gone, or have moved on to another game.
+            let Some(game_info) = state.games.get_mut(&game_id) else {
+                return;
+            };
+            if game_info.archiving != Some(record_id) {
+                return;
+            }
+            // On failure, the next change to the game tries again.
+            game_info.archiving = None;
+            if written.is_ok() {
+                game_info.record_id = Some(record_id);
+                state.broadcast_game_state(game_id).await;
+            }
+        };
+        tokio::spawn(task.in_current_span());
+    }
+
+    /// Refresh the gauges that are read from the state.
+    pub fn update_metrics(&self, metrics: &Metrics) {
+        metrics.connected_clients.set(self.websocket_count() as i64);
+        // A user with several devices is counted once.
+        let authenticated: HashSet<_> = self
+            .clients
+            .values()
+            .filter_map(|client| client.authenticated_as)
+            .collect();
+        metrics.authenticated_users.set(authenticated.len() as i64);
+        for name in GameInfoState::NAMES {
+            metrics.games.with_label_values(&[name]).set(0);
+        }
+        for game_info in self.games.values() {
+            metrics
+                .games
+                .with_label_values(&[game_info.state.name()])
+                .inc();
+        }
+        let drawing_bytes: usize = self
+            .games
+            .values()
+            .flat_map(|game_info| game_info.drawings.values())
+            .map(|drawing| drawing.data.len())
+            .sum();
+        metrics.drawing_bytes.set(drawing_bytes as i64);
+    }
+
+    /// Remove drawings that weren't used as a clue in time, from all games.
+    pub fn collect_unused_drawings(&mut self) {
+        let timeout = Duration::from_secs(self.config.limits.unused_drawing_timeout_secs);
+        for (game_id, game_info) in &mut self.games {
+            let removed = collect_unused_drawings(&mut game_info.drawings, timeout);
+            if removed > 0 {
+                tracing::debug!("Removed {removed} unused drawings from game {game_id:?}");
+            }
+        }
+    }
+
+    /// Validate and store a custom wordlist for a game in lobby state.
+    /// Returns the number of keywords stored.
+    /// `parse` builds the list from what the client sent, given the keyword count of the game.
+    pub async fn set_custom_wordlist(
+        &mut self,
+        game_id: GameId,
+        parse: impl FnOnce(usize) -> Result<Wordlist, String>,
+    ) -> Result<usize, String> {
+        let Some(game_info) = self.games.get_mut(&game_id) else {
+            return Err("Game not found".to_owned());
+        };
+
+        if !matches!(game_info.state, GameInfoState::Lobby) {
+            return Err("Cannot change wordlist while in game".to_owned());
+        }
+
+        let wordlist = parse(game_info.settings.keyword_count)?;
+        let count = wordlist.words.len();
+        game_info.custom_wordlist = Some(Arc::new(wordlist));
+        game_info.global_chat.push(ChatMessage::system(format!(
+            "Custom wordlist with {count} keywords uploaded"
+        )));
+
+        self.broadcast_game_state(game_id).await;
+        Ok(count)
+    }
+
     pub async fn on_message(&mut self, id: ConnectionId, msg: FromClient) -> Result<(), ()> {
+        METRICS.message_handled(&msg);
+        let user_id = self
+            .clients
+            .get(&id)
+            .expect("Should exist")
+            .authenticated_as;
+        let game_id = user_id.and_then(|user_id| self.users[&user_id].game);
+        let span = tracing::info_span!(
+            "message",
+            kind = msg.kind(),
+            user_id = tracing::field::Empty,
+            game_id = tracing::field::Empty,
+        );
+        if let Some(user_id) = user_id {
+            span.record("user_id", tracing::field::display(user_id));
+        }
+        if let Some(game_id) = game_id {
+            span.record("game_id", tracing::field::display(game_id));
+        }
+
+        if let Some(user_id) = user_id {
+            let user_data = self.users.get_mut(&user_id).expect("Should exist");
+            // Bots are trusted.
+            if user_data.bot.is_none() {
+                let verdict = user_data
+                    .rate_limiter
+                    .check_message(&self.config.rate_limits, msg.kind());
+                self.enforce_rate_limit(id, verdict)
+                    .instrument(span.clone())
+                    .await?;
+            }
+        }
+
+        self.handle_message(id, msg).instrument(span).await
+    }
+
+    /// Act on the result of a rate limit check for a connection.
+    /// `Err` if the message must be dropped.
+    pub async fn enforce_rate_limit(
+        &mut self,
+        id: ConnectionId,
+        verdict: Verdict,
+    ) -> Result<(), ()> {
+        match verdict {
+            Verdict::Allow => Ok(()),
+            Verdict::Warn => {
+                tracing::info!("Rate limited");
+                self.send_error(
+                    id,
+                    "You are sending too many messages, slow down",
+                    ErrorSeverity::Warning,
+                )
+                .await;
+                Err(())
+            }
+            Verdict::Drop => Err(()),
+            Verdict::Disconnect => {
+                tracing::warn!("Disconnecting client that keeps exceeding the rate limits");
+                self.close_connection(id, None).await;
+                Err(())
+            }
+        }
+    }
+
+    /// Check whether the user may send a message of the given kind over HTTP instead of the websocket.
+    pub fn check_message_rate(&mut self, user_id: UserId, kind: &'static str) -> Verdict {
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        user_data
+            .rate_limiter
+            .check_message(&self.config.rate_limits, kind)
+    }
+
+    /// Users connected from at least one device, bots included.
+    /// Users that went away don't count toward `max_users`, as they are never removed.
+    fn connected_user_count(&self) -> usize {
+        self.users
+            .values()
+            .filter(|user| user.is_connected())
+            .count()
+    }
+
+    /// Whether the user has joined the game and not left it.
+    pub fn is_in_game(&self, user_id: UserId, game_id: GameId) -> bool {
+        self.users[&user_id].game == Some(game_id)
+    }
+
+    /// Check whether the user may upload another drawing.
+    pub fn check_drawing_upload(&mut self, user_id: UserId) -> Verdict {
+        let user_data = self.users.get_mut(&user_id).expect("Should exist");
+        user_data
+            .rate_limiter
+            .check_drawing_upload(&self.config.rate_limits)
+    }
+
+    async fn handle_message(&mut self, id: ConnectionId, msg: FromClient) -> Result<(), ()> {
+        tracing::debug!("Handling message");
         match msg {
             FromClient::Auth { secret } => {
                 let user_id = if let Some(secret) = secret {
                     if let Some(user_id) = self.find_client_by_secret(secret) {
-                        log::debug!("Client {id:?} auth ok, user {user_id:?}");
-                        self.users
-                            .get_mut(&user_id)
-                            .expect("Should exist")
-                            .connection_id = Some(id);
+                        if self.users.get(&user_id).expect("Should exist").banned {
+                            tracing::info!(%user_id, "Rejected banned user");
+                            self.send_error(
+                                id,
+                                "You are banned from this server",
+                                ErrorSeverity::Error,
+                            )
+                            .await;
+                            return Err(());
+                        }
+                        tracing::debug!(%user_id, "Auth ok");
                         Some(user_id)
                     } else {
-                        log::debug!("Client {id:?} secret not recognized");
+                        tracing::debug!("Secret not recognized");
                         self.send_to_connection(
                             id,
                             ToClient::Error {
@@ -382,53 +1264,106 @@ impl State {
                         None
                     }
                 } else {
-                    log::debug!("Client {id:?} auth without secret");
+                    tracing::debug!("Auth without secret");
                     None
                 };
 
                 let user_id = match user_id {
                     Some(user_id) => user_id,
                     None => {
+                        if let Some(max_users) = self.config.limits.max_users
+                            && self.connected_user_count() >= max_users
+                        {
+                            self.send_error(
+                                id,
+                                "Server is full, please try again later",
+                                ErrorSeverity::Error,
+                            )
+                            .await;
+                            return Err(());
+                        }
+
                         // Create a new user with a random secret.
                         let user_id = UserId::new();
                         let secret = UserSecret::new();
-                        log::debug!("Client {id:?} creating new user, id {user_id:?}");
+                        tracing::info!(%user_id, "Created user");
                         self.users.insert(
                             user_id,
                             UserData {
-                                connection_id: Some(id),
+                                connections: HashSet::new(),
                                 secret,
                                 nick: None,
                                 game: None,
+                                bot: None,
+                                banned: false,
+                                rate_limiter: RateLimiter::default(),
                             },
                         );
+                        // Saved once they pick a nick.
                         user_id
                     }
                 };
 
-                self.clients
-                    .get_mut(&id)
-                    .expect("Should exist")
-                    .authenticated_as = Some(user_id);
+                self.attach_connection(id, user_id).await;
+                Ok(())
+            }
+            FromClient::RequestPairingCode { revoke_others } => {
+                let user_id = self.require_auth(id).await?;
+                let ttl = Duration::from_secs(self.config.limits.pairing_code_ttl_secs);
+                let code = self.pairing_codes.create(user_id, revoke_others, ttl);
+                tracing::info!(revoke_others, "Created pairing code");
+                self.send_to_connection(
+                    id,
+                    ToClient::PairingCode {
+                        code,
+                        expires_in_secs: ttl.as_secs(),
+                        revoke_others,
+                    },
+                )
+                .await;
+                Ok(())
+            }
+            FromClient::RedeemPairingCode(code) => {
+                let Some(pairing) = self.pairing_codes.redeem(&code) else {
+                    self.send_error(id, "Invalid or expired pairing code", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                };
+                let user_id = pairing.user_id;
+                let user_data = self.users.get_mut(&user_id).expect("Should exist");
+                if user_data.banned {
+                    self.send_error(id, "You are banned from this server", ErrorSeverity::Error)
+                        .await;
+                    return Err(());
+                }
+                tracing::info!(%user_id, revoke_others = pairing.revoke_others, "Redeemed pairing code");
 
-                self.send_state_to_user(user_id).await;
+                if pairing.revoke_others {
+                    // The old devices still have the secret, so it must change for them to stay logged out.
+                    user_data.secret = UserSecret::new();
+                    self.persist_users();
+                    self.revoke_connections(user_id, id).await;
+                }
+                self.attach_connection(id, user_id).await;
                 Ok(())
             }
             FromClient::SetNick(nick) => {
                 let user_id = self.require_auth(id).await?;
-                if nick.len() < 2 || nick.len() > 64 {
-                    self.send_error(
-                        id,
-                        "Nickname must be between 2 and 64 characters",
-                        ErrorSeverity::Info,
-                    )
-                    .await;
+                let limits = &self.config.limits;
+                if nick.len() < limits.min_nick_len || nick.len() > limits.max_nick_len {
+                    let message = format!(
+                        "Nickname must be between {} and {} characters",
+                        limits.min_nick_len, limits.max_nick_len
+                    );
+                    self.send_error(id, message, ErrorSeverity::Info).await;
                     return Ok(());
                 }
 
                 let user_data = self.users.get_mut(&user_id).expect("Should exist");
                 let old_nick = user_data.nick.replace(nick);
-                if let Some(game_id) = user_data.game {
+                let game = user_data.game;
+                self.persist_users();
+                if let Some(game_id) = game {
                     let old_nick = old_nick.expect("In game, should have a nick");
                     self.games
                         .get_mut(&game_id)
@@ -458,15 +1393,27 @@ impl State {
                     return Ok(());
                 }
 
+                if let Some(max_lobbies) = self.config.limits.max_lobbies
+                    && self.games.len() >= max_lobbies
+                {
+                    self.send_error(
+                        id,
+                        "Too many lobbies open, please try again later",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Ok(());
+                }
+
                 let game_id = GameId::new();
-                log::debug!("Client {id:?} creating a new lobby {game_id:?}");
 
-                let mut game_info = GameInfo::default();
+                let mut game_info = GameInfo::new(self.config.default_settings.clone());
                 game_info.add_player(user_id);
                 game_info.global_chat.push(ChatMessage::system(format!(
                     "<{user_id}> created a new lobby"
                 )));
                 self.games.insert(game_id, game_info);
+                tracing::info!(%game_id, "Created lobby");
 
                 self.users.get_mut(&user_id).expect("Should exist").game = Some(game_id);
 
@@ -533,6 +1480,7 @@ impl State {
                 game_info
                     .global_chat
                     .push(ChatMessage::system(format!("<{user_id}> joined the lobby")));
+                tracing::info!(%game_id, "Joined lobby");
 
                 self.broadcast_game_state(game_id).await;
                 Ok(())
@@ -549,6 +1497,7 @@ impl State {
                 game_info
                     .global_chat
                     .push(ChatMessage::system(format!("<{user_id}> left the lobby")));
+                tracing::info!("Left lobby");
 
                 self.broadcast_game_state(game_id).await;
                 self.send_state_to_user(user_id).await;
@@ -572,6 +1521,7 @@ impl State {
                     .hack_players_mut()
                     .get_mut(&user_id)
                     .expect("Should exist") = GamePlayerInfo::InTeam(team);
+                tracing::info!(team = team.index(), "Joined team");
                 self.broadcast_game_state(game_id).await;
                 Ok(())
             }
@@ -586,9 +1536,10 @@ impl State {
                     return Err(());
                 }
 
-                // Only allow kicking users that are not connected, for now.
+                // Only allow kicking users that are not connected, or bots, for now.
                 let kick_user_data = self.users.get(&kick_user_id).expect("Should exist");
-                if kick_user_data.connection_id.is_some() {
+                let is_bot = kick_user_data.bot.is_some();
+                if kick_user_data.is_connected() && !is_bot {
                     self.send_error(
                         id,
                         "You can only kick users that are not connected",
@@ -601,12 +1552,114 @@ impl State {
                 // Mark player as kicked. If in game, store the team so they must re-join it again if joining later.
                 game_info.kick_player(kick_user_id);
 
-                game_info.global_chat.push(ChatMessage::system(format!(
-                    "Disconnected <{kick_user_id}> was kicked the game by <{user_id}>"
-                )));
+                game_info.global_chat.push(ChatMessage::system(if is_bot {
+                    format!("Bot <{kick_user_id}> was removed from the game by <{user_id}>")
+                } else {
+                    format!("Disconnected <{kick_user_id}> was kicked the game by <{user_id}>")
+                }));
+                tracing::info!(kicked_user_id = %kick_user_id, is_bot, "Kicked player");
 
                 let kick_user_data = self.users.get_mut(&kick_user_id).expect("Should exist");
                 kick_user_data.game = None;
+                if is_bot {
+                    self.remove_bot(kick_user_id);
+                }
+                self.broadcast_game_state(game_id).await;
+                Ok(())
+            }
+            FromClient::AddBot { team, kind } => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                let available = match kind {
+                    BotKind::Association { .. } => self.config.bot_model.is_some(),
+                    BotKind::Llm => self.config.llm.is_some(),
+                };
+                let Some(bots) = self.bots.clone().filter(|_| available) else {
+                    self.send_error(
+                        id,
+                        "This kind of bot is not available on this server",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                };
+                let game_info = self.games.get_mut(&game_id).expect("Should exist");
+                if !matches!(game_info.state, GameInfoState::Lobby) {
+                    self.send_error(id, "Cannot add bots while in game", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                }
+                if matches!(game_info.settings.clue_mode, ClueMode::Draw) {
+                    self.send_error(id, "Bots cannot draw clues", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                }
+                // Bots are connected users too.
+                if let Some(max_users) = self.config.limits.max_users
+                    && self.connected_user_count() >= max_users
+                {
+                    self.send_error(
+                        id,
+                        "Server is full, cannot add more bots",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                }
+                let game_info = self.games.get_mut(&game_id).expect("Should exist");
+
+                let bot_id = UserId::new();
+                let connection_id = ConnectionId::new();
+                let (sender, outbound) = mpsc::unbounded_channel();
+                let number = game_info
+                    .players()
+                    .keys()
+                    .filter(|player| self.users[player].bot.is_some())
+                    .count()
+                    + 1;
+
+                game_info.add_player(bot_id);
+                *game_info
+                    .hack_players_mut()
+                    .get_mut(&bot_id)
+                    .expect("Should exist") = GamePlayerInfo::InTeam(team);
+                tracing::info!(bot_id = %bot_id, team = team.index(), %kind, "Added bot");
+                game_info.global_chat.push(ChatMessage::system(format!(
+                    "<{user_id}> added bot <{bot_id}>"
+                )));
+
+                self.clients.insert(
+                    connection_id,
+                    ClientData {
+                        outbound: Outbound::Bot(sender),
+                        authenticated_as: Some(bot_id),
+                        device: "Bot".to_owned(),
+                        connected_at: Instant::now(),
+                    },
+                );
+                self.users.insert(
+                    bot_id,
+                    UserData {
+                        connections: HashSet::from([connection_id]),
+                        secret: UserSecret::new(),
+                        nick: Some(format!("Bot {number} ({kind})")),
+                        game: Some(game_id),
+                        bot: Some(kind),
+                        banned: false,
+                        rate_limiter: RateLimiter::default(),
+                    },
+                );
+                let bot = NewBot {
+                    connection_id,
+                    user_id: bot_id,
+                    kind,
+                    outbound,
+                };
+                if bots.send(bot).is_err() {
+                    tracing::error!("Bots are no longer being played");
+                }
+
                 self.broadcast_game_state(game_id).await;
                 Ok(())
             }
@@ -625,23 +1678,54 @@ impl State {
                     return Err(());
                 }
 
-                if let Err(err) = settings.validate() {
+                if let Err(err) =
+                    settings.validate(&self.wordlists, game_info.custom_wordlist.as_ref())
+                {
                     self.send_error(id, format!("Invalid settings: {err}"), ErrorSeverity::Info)
                         .await;
                     return Err(());
                 }
 
+                // Bots only give text clues, and would stall their team.
+                let has_bots = game_info.players().iter().any(|(player_id, info)| {
+                    self.users[player_id].bot.is_some()
+                        && !matches!(info, GamePlayerInfo::LeftGame(_))
+                });
+                if has_bots && matches!(settings.clue_mode, ClueMode::Draw) {
+                    self.send_error(
+                        id,
+                        "Bots cannot draw clues, remove them before allowing only drawings",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                }
+
                 game_info.settings = settings;
+                tracing::info!(settings = ?game_info.settings, "Changed settings");
 
                 self.broadcast_game_state(game_id).await;
                 Ok(())
             }
+            FromClient::SetCustomWordlist(words) => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                let parse = |keyword_count| custom_wordlist_from_words(words, keyword_count);
+                if let Err(err) = self.set_custom_wordlist(game_id, parse).await {
+                    self.send_error(id, format!("Invalid wordlist: {err}"), ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                }
+
+                Ok(())
+            }
             FromClient::StartGame => {
                 let user_id = self.require_auth(id).await?;
                 let game_id = self.require_game(id, user_id).await?;
 
                 let game_info = self.games.get_mut(&game_id).expect("Should exist");
-                if let Err(e) = game_info.startable() {
+                if let Err(e) = game_info.start(&self.wordlists) {
                     self.send_to_connection(
                         id,
                         ToClient::Error {
@@ -652,18 +1736,95 @@ impl State {
                     .await;
                     return Err(());
                 }
+                tracing::info!(
+                    language = %game_info.language,
+                    keyword_count = game_info.settings.keyword_count,
+                    "Game started"
+                );
+
+                self.broadcast_game_state(game_id).await;
+                Ok(())
+            }
+            FromClient::ReturnToLobby => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                let game_info = self.games.get_mut(&game_id).expect("Should exist");
+                if let Err(e) = game_info.return_to_lobby() {
+                    self.send_error(id, e, ErrorSeverity::Info).await;
+                    return Err(());
+                }
+
+                game_info.global_chat.push(ChatMessage::system(format!(
+                    "<{user_id}> returned the game to the lobby"
+                )));
+                tracing::info!("Returned to lobby");
+                self.broadcast_game_state(game_id).await;
+                Ok(())
+            }
+            FromClient::ClearKeywordHistory => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                let game_info = self.games.get_mut(&game_id).expect("Should exist");
+                if !matches!(game_info.state, GameInfoState::Lobby) {
+                    self.send_error(
+                        id,
+                        "Cannot clear keyword history while in game",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                }
 
-                game_info.start();
+                game_info.keyword_history.clear();
+                game_info.global_chat.push(ChatMessage::system(format!(
+                    "<{user_id}> cleared the keyword history"
+                )));
                 self.broadcast_game_state(game_id).await;
                 Ok(())
             }
+            FromClient::ExportGame(format) => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                match self.export_game(game_id) {
+                    Ok(pending) => {
+                        let shared = self.shared.clone();
+                        let task = async move {
+                            let content = pending.load().await.render(format);
+                            let Some(state) = shared.upgrade() else {
+                                return;
+                            };
+                            let mut state = metrics::lock(&state).await;
+                            // The client may have left while the drawings were read.
+                            if state.clients.contains_key(&id) {
+                                state
+                                    .send_to_connection(id, ToClient::Export { format, content })
+                                    .await;
+                            }
+                        };
+                        tokio::spawn(task.in_current_span());
+                        Ok(())
+                    }
+                    Err(err) => {
+                        self.send_error(id, err, ErrorSeverity::Info).await;
+                        Err(())
+                    }
+                }
+            }
             FromClient::SubmitClues(clues) => {
                 let user_id = self.require_auth(id).await?;
                 let game_id = self.require_game(id, user_id).await?;
 
                 let game_info = self.games.get_mut(&game_id).expect("Should exist");
                 if let Some(team) = game_info.team_for_user(user_id) {
-                    if let GameInfoState::InGame { current_round, .. } = &mut game_info.state {
+                    if let GameInfoState::InGame {
+                        current_round,
+                        keywords,
+                        ..
+                    } = &mut game_info.state
+                    {
                         let GameInfoStateCurrentRound::Normal(current_round) = current_round else {
                             self.send_error(
                                 id,
@@ -696,13 +1857,51 @@ impl State {
                             return Err(());
                         }
 
+                        let mut used_drawings = HashSet::new();
+                        for clue in &clues {
+                            let result = match clue {
+                                Clue::Text(_)
+                                    if matches!(game_info.settings.clue_mode, ClueMode::Draw) =>
+                                {
+                                    Err("Only drawn clues are allowed in this game".to_owned())
+                                }
+                                Clue::Text(text) if text.trim().is_empty() => {
+                                    Err("Clues must not be empty".to_owned())
+                                }
+                                Clue::Text(text)
+                                    if keywords[team].iter().any(|keyword| {
+                                        game_info.language.contains_keyword(text, keyword)
+                                    }) =>
+                                {
+                                    Err("Clues must not contain your team's keywords".to_owned())
+                                }
+                                Clue::Text(_) => Ok(()),
+                                Clue::Drawing(_) | Clue::Strokes(_)
+                                    if matches!(game_info.settings.clue_mode, ClueMode::Text) =>
+                                {
+                                    Err("Only text clues are allowed in this game".to_owned())
+                                }
+                                Clue::Drawing(drawing_id) | Clue::Strokes(drawing_id)
+                                    if !used_drawings.insert(*drawing_id) =>
+                                {
+                                    Err("The same drawing cannot be used twice".to_owned())
+                                }
+                                Clue::Drawing(drawing_id) | Clue::Strokes(drawing_id) => {
+                                    check_drawing_owned(&game_info.drawings, user_id, *drawing_id)
+                                        .and_then(|()| check_clue_format(&game_info.drawings, clue))
+                                }
+                            };
+
+                            if let Err(err) = result {
+                                self.send_error(id, err, ErrorSeverity::Info).await;
+                                return Err(());
+                            }
+                        }
+
                         // TODO: Validate clues.
                         // * Reusing clues is not allowed.
                         // * Clues must be unique.
-                        // * Clues must be valid according to the game settings.
-                        // * Clues must not be empty.
                         // * Clues must not be too long.
-                        // * Clues must not contain team keywords.
 
                         // Check for resubmission.
                         if current_round[team].clues.is_some() {
@@ -721,6 +1920,15 @@ impl State {
                                 .collect::<Vec<_>>()
                                 .join(", ")
                         )));
+                        // Drawings used as clues must stay as they were when submitted.
+                        for drawing_id in used_drawings {
+                            if let Some(drawing) = game_info.drawings.get_mut(&drawing_id) {
+                                drawing.locked = true;
+                            }
+                        }
+                        current_round[team].encrypt_millis = current_round[team]
+                            .started_at
+                            .map(|at| at.elapsed().as_millis() as u64);
                         current_round[team].clues = Some(clues);
                         self.broadcast_game_state(game_id).await;
                         Ok(())
@@ -787,7 +1995,9 @@ impl State {
                             game_info.global_chat.push(ChatMessage::system(format!(
                                 "Round ended, scores:\n{result}"
                             )));
+                            tracing::info!(?result, "Round ended");
                         }
+                        self.archive_if_finished(game_id);
                         self.broadcast_game_state(game_id).await;
                         Ok(())
                     } else {
@@ -858,7 +2068,9 @@ impl State {
                             game_info.global_chat.push(ChatMessage::system(format!(
                                 "Round ended, scores:\n{result}"
                             )));
+                            tracing::info!(?result, "Round ended");
                         }
+                        self.archive_if_finished(game_id);
                         self.broadcast_game_state(game_id).await;
                         Ok(())
                     } else {
@@ -920,7 +2132,9 @@ impl State {
                         game_info.global_chat.push(ChatMessage::system(format!(
                             "Tiebreaker ended, scores:\n{scores}",
                         )));
+                        tracing::info!(?scores, "Tiebreaker ended");
                     }
+                    self.archive_if_finished(game_id);
                     self.broadcast_game_state(game_id).await;
                     Ok(())
                 } else {
@@ -929,6 +2143,86 @@ impl State {
                     Err(())
                 }
             }
+            FromClient::RequestSuggestions { intercept, count } => {
+                let user_id = self.require_auth(id).await?;
+                let game_id = self.require_game(id, user_id).await?;
+
+                let Some(model) = self.model.clone() else {
+                    self.send_error(
+                        id,
+                        "Suggestions are not available on this server",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                };
+                let game_info = self.games.get(&game_id).expect("Should exist");
+                if !game_info.settings.suggestions {
+                    self.send_error(
+                        id,
+                        "Suggestions are disabled in this game",
+                        ErrorSeverity::Info,
+                    )
+                    .await;
+                    return Err(());
+                }
+                let Some(team) = game_info.team_for_user(user_id) else {
+                    self.send_error(id, "You are not in a team", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                };
+                let GameInfoState::InGame {
+                    keywords,
+                    completed_rounds,
+                    current_round: GameInfoStateCurrentRound::Normal(current_round),
+                    ..
+                } = &game_info.state
+                else {
+                    self.send_error(id, "No round in progress", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                };
+
+                let target = if intercept { team.other() } else { team };
+                let Some(clues) = &current_round[target].clues else {
+                    self.send_error(id, "No clues given yet", ErrorSeverity::Info)
+                        .await;
+                    return Err(());
+                };
+
+                let mut slots = clue_history(
+                    completed_rounds.iter().map(|round| &round[target]),
+                    game_info.settings.keyword_count,
+                );
+                if !intercept {
+                    // The team knows its own keywords.
+                    for (words, keyword) in slots.iter_mut().zip(&keywords[team]) {
+                        words.push(keyword.to_lowercase());
+                    }
+                }
+                let scores = slot_scores(&model, clues, &slots);
+                let count = count.unwrap_or(DEFAULT_SUGGESTIONS).min(MAX_SUGGESTIONS);
+                let round = completed_rounds.len();
+
+                match rank_codes(&scores, count) {
+                    Ok(suggestions) => {
+                        self.send_to_connection(
+                            id,
+                            ToClient::Suggestions {
+                                intercept,
+                                round,
+                                suggestions,
+                            },
+                        )
+                        .await;
+                        Ok(())
+                    }
+                    Err(err) => {
+                        self.send_error(id, err, ErrorSeverity::Info).await;
+                        Err(())
+                    }
+                }
+            }
             FromClient::TriggerTimers => {
                 let user_id = self.require_auth(id).await?;
                 let game_id = self.require_game(id, user_id).await?;
@@ -1032,7 +2326,7 @@ impl State {
                 let user_id = self.require_auth(id).await?;
                 let game_id = self.require_game(id, user_id).await?;
 
-                if message.len() >= 4096 {
+                if message.len() > self.config.limits.max_chat_message_bytes {
                     self.send_error(id, "Message too long", ErrorSeverity::Info)
                         .await;
                     return Err(());
@@ -1049,3 +2343,32 @@ impl State {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_are_named_from_user_agents() {
+        assert_eq!(
+            device_name(Some(
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Mobile Safari/537.36"
+            )),
+            "Chrome on Android"
+        );
+        assert_eq!(
+            device_name(Some(
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1"
+            )),
+            "Safari on iPhone"
+        );
+        assert_eq!(
+            device_name(Some(
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/128.0.0.0 Safari/537.36 Edg/128.0.0.0"
+            )),
+            "Edge on Windows"
+        );
+        assert_eq!(device_name(Some("curl/8.5.0")), "Unknown device");
+        assert_eq!(device_name(None), "Unknown device");
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    decrypto::{
+        Team,
+        record::{GameRecord, RecordPlayer},
+    },
+    id::{GameId, RecordId, UserId},
+    rating::Ratings,
+    stats::{self, PlayerStats},
+};
+
+/// Summary of an archived game, kept in memory for listing the history.
+/// The full record stays on disk.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ArchivedGame {
+    pub id: RecordId,
+    pub game_id: GameId,
+    pub finished_at: u64,
+    pub players: Vec<RecordPlayer>,
+    pub winner: Option<Team>,
+}
+
+impl From<&GameRecord> for ArchivedGame {
+    fn from(record: &GameRecord) -> Self {
+        Self {
+            id: record.id,
+            game_id: record.game_id,
+            finished_at: record.finished_at,
+            players: record.players.clone(),
+            winner: record.winner,
+        }
+    }
+}
+
+/// Finished games, stored as one JSON file per game.
+#[derive(Debug)]
+pub struct Archive {
+    dir: PathBuf,
+    /// Oldest first.
+    games: Vec<ArchivedGame>,
+    /// Built from all archived games.
+    stats: HashMap<UserId, PlayerStats>,
+    /// Built from all archived games, in the order they finished.
+    ratings: Ratings,
+}
+
+impl Archive {
+    /// Loads the summaries of all games in the directory, creating it if needed.
+    /// Unreadable records are logged and skipped.
+    pub fn load(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut games = Vec::new();
+        let mut stats = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let record = fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    serde_json::from_slice::<GameRecord>(&bytes).map_err(|e| e.to_string())
+                });
+            match record {
+                Ok(record) => {
+                    stats::add_game(&mut stats, &record);
+                    games.push(ArchivedGame::from(&record));
+                }
+                Err(err) => tracing::error!("Skipping archived game {}: {err}", path.display()),
+            }
+        }
+        games.sort_by_key(|game| game.finished_at);
+        let mut ratings = Ratings::default();
+        for game in &games {
+            ratings.add_game(game);
+        }
+
+        tracing::info!(
+            "Loaded {} archived games from {}",
+            games.len(),
+            dir.display()
+        );
+        Ok(Self {
+            dir,
+            games,
+            stats,
+            ratings,
+        })
+    }
+
+    fn path(dir: &Path, id: RecordId) -> PathBuf {
+        dir.join(format!("{}.json", id.0))
+    }
+
+    /// Newest games first, optionally only those the player took part in.
+    pub fn history(
+        &self,
+        player: Option<UserId>,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<&ArchivedGame> {
+        self.games
+            .iter()
+            .rev()
+            .filter(|game| player.is_none_or(|player| game.players.iter().any(|p| p.id == player)))
+            .skip(offset)
+            .take(limit)
+            .collect()
+    }
+
+    /// Path of the full record, if the game is in the archive.
+    pub fn record_path(&self, id: RecordId) -> Option<PathBuf> {
+        self.games
+            .iter()
+            .any(|game| game.id == id)
+            .then(|| Self::path(&self.dir, id))
+    }
+
+    /// Directory of the records, for [`Archive::write`].
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Stores the record in the directory. Doesn't need the archive, so that it can be done
+    /// without holding the state lock; [`Archive::add`] it afterwards.
+    pub async fn write(dir: &Path, record: &GameRecord) -> io::Result<()> {
+        let path = Self::path(dir, record.id);
+        let json = serde_json::to_vec(record).expect("Should serialize");
+
+        // Write to a temporary file first, so a crash never leaves a partial record behind.
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, json).await?;
+        tokio::fs::rename(&tmp, &path).await
+    }
+
+    /// Lists a record written with [`Archive::write`], and counts it in the stats and ratings.
+    pub fn add(&mut self, record: &GameRecord) {
+        let game = ArchivedGame::from(record);
+        stats::add_game(&mut self.stats, record);
+        self.ratings.add_game(&game);
+        self.games.push(game);
+    }
+
+    /// Whether the user played in any archived game.
+    pub fn has_played(&self, user_id: UserId) -> bool {
+        self.stats.contains_key(&user_id)
+    }
+
+    pub fn stats(&self, user_id: UserId) -> PlayerStats {
+        self.stats.get(&user_id).cloned().unwrap_or_default()
+    }
+
+    pub fn ratings(&self) -> &Ratings {
+        &self.ratings
+    }
+}
+
+pub async fn read_record(path: &Path) -> io::Result<GameRecord> {
+    let bytes = tokio::fs::read(path).await?;
+    serde_json::from_slice(&bytes).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::decrypto::{PerTeam, settings::GameSettings, wordlist::Language};
+
+    fn record(player: UserId, finished_at: u64) -> GameRecord {
+        GameRecord {
+            id: RecordId::new(),
+            game_id: GameId::new(),
+            finished_at,
+            settings: GameSettings::default(),
+            language: Language::default(),
+            players: vec![RecordPlayer {
+                id: player,
+                nick: "nick".to_owned(),
+                team: Some(Team::WHITE),
+                is_bot: false,
+            }],
+            winner: None,
+            keywords: PerTeam::splat(Vec::new()),
+            rounds: Vec::new(),
+            tiebreaker: None,
+            drawings: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn archived_games_are_persisted() {
+        let dir = std::env::temp_dir().join(format!("decrypto-archive-{}", uuid::Uuid::new_v4()));
+        let (alice, bob) = (UserId::new(), UserId::new());
+
+        let mut archive = Archive::load(&dir).unwrap();
+        let first = record(alice, 1);
+        for record in [&first, &record(bob, 2)] {
+            Archive::write(archive.dir(), record).await.unwrap();
+            archive.add(record);
+        }
+
+        let archive = Archive::load(&dir).unwrap();
+        let history = archive.history(None, 0, 10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].finished_at, 2);
+        assert_eq!(archive.history(Some(alice), 0, 10)[0].id, first.id);
+
+        let path = archive.record_path(first.id).unwrap();
+        assert_eq!(read_record(&path).await.unwrap().game_id, first.game_id);
+        assert!(archive.record_path(RecordId::new()).is_none());
+        assert_eq!(archive.stats(alice).games_played, 1);
+        // The only player has no opponents, so the game is not rated.
+        assert!(archive.ratings().leaderboard(10).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
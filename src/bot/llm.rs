@@ -0,0 +1,442 @@
+use std::{collections::HashSet, fmt::Write, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::LlmConfig,
+    decrypto::{Code, PerTeam, Team},
+    id::UserId,
+    message::{Clue, CompletedRoundPerTeam, FromClient, GameStateView, GameView, Inputs},
+};
+
+const SYSTEM_PROMPT: &str = "\
+You are playing the board game Decrypto. Each team has secret numbered keywords. \
+Each round, one player of a team gets a secret code of keyword numbers and gives one clue per number, \
+so that their teammates can guess the code but the other team cannot. \
+Teams guess their own code from the clues, and try to intercept the other team's code \
+from the clues that team gave in earlier rounds. \
+Clues must be short and must not contain the keywords. \
+Always answer with a single JSON object and nothing else.";
+
+/// Wait after the first failed request, doubled after each further failure.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize)]
+struct LlmMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [LlmMessage],
+    max_tokens: u32,
+    temperature: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionMessage {
+    content: String,
+}
+
+/// Plays by asking a language model behind an OpenAI-compatible chat completions endpoint.
+/// If the model keeps giving invalid answers, the player does nothing and its team times out.
+#[derive(Debug)]
+pub struct LlmPlayer {
+    client: reqwest::Client,
+    config: LlmConfig,
+}
+
+impl LlmPlayer {
+    pub fn new(config: LlmConfig) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self { client, config })
+    }
+
+    /// Messages the player sends in reaction to the game state.
+    pub async fn decide(&self, user_id: UserId, game: &GameView) -> Vec<FromClient> {
+        let GameStateView::InGame {
+            completed_rounds,
+            current_round,
+            keywords,
+            inputs,
+        } = &game.state
+        else {
+            return Vec::new();
+        };
+        let Some(team) = game
+            .players
+            .iter()
+            .find(|p| p.id == user_id)
+            .and_then(|p| p.team)
+        else {
+            return Vec::new();
+        };
+        let clue_count = game.settings.clue_count;
+        let keyword_count = game.settings.keyword_count;
+        let context = describe_game(keywords, completed_rounds, team);
+
+        match inputs {
+            Inputs::Encrypt { code, .. } => {
+                let prompt = format!(
+                    "{context}\nYou are giving clues this round. Your code is {code}. \
+                     Give {clue_count} clues, one for each keyword number of the code in order. \
+                     Don't reuse earlier clues. \
+                     Answer as {{\"clues\": [\"first clue\", ...]}}."
+                );
+                self.ask(prompt, |reply| parse_clues(reply, clue_count, keywords))
+                    .await
+                    .map(FromClient::SubmitClues)
+                    .into_iter()
+                    .collect()
+            }
+            Inputs::Guess {
+                decipher,
+                intercept,
+                ..
+            } => {
+                let Some(round) = current_round else {
+                    return Vec::new();
+                };
+                let mut actions = Vec::new();
+                if *decipher && let Some(clues) = &round[team].clues {
+                    let prompt = format!(
+                        "{context}\nYour teammate gave the clues {} this round. \
+                         Which of your keywords does each clue point to? \
+                         Answer with the keyword numbers, from 1 to {keyword_count}, \
+                         as {{\"code\": [1, 2, 3]}}.",
+                        clue_list(clues)
+                    );
+                    if let Some(code) = self
+                        .ask(prompt, |reply| parse_code(reply, clue_count, keyword_count))
+                        .await
+                    {
+                        actions.push(FromClient::SubmitDecipher(code));
+                    }
+                }
+                if *intercept && let Some(clues) = &round[team.other()].clues {
+                    let prompt = format!(
+                        "{context}\nThe other team gave the clues {} this round. \
+                         Based on their earlier clues, guess which of their keyword numbers \
+                         each clue points to, from 1 to {keyword_count}. \
+                         Answer as {{\"code\": [1, 2, 3]}}.",
+                        clue_list(clues)
+                    );
+                    if let Some(code) = self
+                        .ask(prompt, |reply| parse_code(reply, clue_count, keyword_count))
+                        .await
+                    {
+                        actions.push(FromClient::SubmitIntercept(code));
+                    }
+                }
+                actions
+            }
+            Inputs::Tiebreaker { submitted, .. } => {
+                let prompt = format!(
+                    "{context}\nThe game is tied. Guess the other team's {keyword_count} keywords \
+                     from the clues they gave, in keyword number order. \
+                     Answer as {{\"keywords\": [\"first keyword\", ...]}}."
+                );
+                let Some(guesses) = self
+                    .ask(prompt, |reply| parse_keywords(reply, keyword_count))
+                    .await
+                else {
+                    return Vec::new();
+                };
+                guesses
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| submitted[team][*index].is_none())
+                    .map(|(index, guess)| FromClient::SubmitTiebreaker { index, guess })
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Asks the model until it gives a valid answer, telling it what was wrong with the earlier ones.
+    async fn ask<T>(&self, prompt: String, parse: impl Fn(&str) -> Result<T, String>) -> Option<T> {
+        let mut messages = vec![
+            LlmMessage {
+                role: "system",
+                content: SYSTEM_PROMPT.to_owned(),
+            },
+            LlmMessage {
+                role: "user",
+                content: prompt,
+            },
+        ];
+
+        let mut retry_delay = RETRY_DELAY;
+        for attempt in 1..=self.config.max_attempts {
+            let reply = match self.complete(&messages).await {
+                Ok(reply) => reply,
+                Err(err) => {
+                    tracing::warn!("LLM request failed (attempt {attempt}): {err}");
+                    // Don't hammer an endpoint that is down.
+                    if attempt < self.config.max_attempts {
+                        tokio::time::sleep(retry_delay).await;
+                        retry_delay *= 2;
+                    }
+                    continue;
+                }
+            };
+            match parse(&reply) {
+                Ok(answer) => return Some(answer),
+                Err(err) => {
+                    tracing::debug!("Invalid LLM answer (attempt {attempt}): {err}: {reply:?}");
+                    messages.push(LlmMessage {
+                        role: "assistant",
+                        content: reply,
+                    });
+                    messages.push(LlmMessage {
+                        role: "user",
+                        content: format!("That answer is invalid: {err}. Answer again."),
+                    });
+                }
+            }
+        }
+
+        tracing::warn!(
+            "LLM player gave up after {} attempts",
+            self.config.max_attempts
+        );
+        None
+    }
+
+    async fn complete(&self, messages: &[LlmMessage]) -> Result<String, reqwest::Error> {
+        let url = format!("{}/chat/completions", self.config.url.trim_end_matches('/'));
+        let mut request = self.client.post(url).json(&CompletionRequest {
+            model: &self.config.model,
+            messages,
+            max_tokens: self.config.max_tokens,
+            temperature: self.config.temperature,
+        });
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key.expose());
+        }
+
+        let response: CompletionResponse = request.send().await?.error_for_status()?.json().await?;
+        Ok(response
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .unwrap_or_default())
+    }
+}
+
+/// What the player knows about the game: their keywords and the clues of both teams so far.
+fn describe_game(
+    keywords: &[String],
+    completed_rounds: &[PerTeam<CompletedRoundPerTeam>],
+    team: Team,
+) -> String {
+    let mut text = String::from("Your team's keywords are:\n");
+    for (index, keyword) in keywords.iter().enumerate() {
+        let _ = writeln!(text, "{}. {keyword}", index + 1);
+    }
+
+    for (heading, team) in [
+        ("Clues your team gave in earlier rounds", team),
+        ("Clues the other team gave in earlier rounds", team.other()),
+    ] {
+        let _ = writeln!(text, "{heading}:");
+        if completed_rounds.is_empty() {
+            let _ = writeln!(text, "none yet");
+        }
+        for (number, round) in completed_rounds.iter().enumerate() {
+            let round = &round[team].non_computed;
+            let clues = match &round.clues {
+                Some(clues) => clue_list(clues),
+                None => "none, they ran out of time".to_owned(),
+            };
+            let _ = writeln!(
+                text,
+                "Round {}: code {}, clues {clues}",
+                number + 1,
+                round.code
+            );
+        }
+    }
+    text
+}
+
+fn clue_list(clues: &[Clue]) -> String {
+    clues
+        .iter()
+        .map(|clue| match clue {
+            Clue::Text(text) => format!("\"{text}\""),
+            Clue::Drawing(_) | Clue::Strokes(_) => "(a drawing)".to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The JSON object in the reply, ignoring any text around it.
+fn reply_json(reply: &str) -> Result<Value, String> {
+    let (Some(start), Some(end)) = (reply.find('{'), reply.rfind('}')) else {
+        return Err("no JSON object found".to_owned());
+    };
+    if end < start {
+        return Err("no JSON object found".to_owned());
+    }
+    serde_json::from_str(&reply[start..=end]).map_err(|e| format!("invalid JSON: {e}"))
+}
+
+fn string_list(reply: &str, key: &str, count: usize) -> Result<Vec<String>, String> {
+    let json = reply_json(reply)?;
+    let list: Vec<String> = json
+        .get(key)
+        .and_then(|list| serde_json::from_value(list.clone()).ok())
+        .ok_or_else(|| format!("expected \"{key}\" to be a list of strings"))?;
+    if list.len() != count {
+        return Err(format!("expected {count} {key}, got {}", list.len()));
+    }
+    let list: Vec<_> = list.into_iter().map(|s| s.trim().to_owned()).collect();
+    if list.iter().any(|s| s.is_empty()) {
+        return Err(format!("{key} must not be empty"));
+    }
+    Ok(list)
+}
+
+fn parse_clues(reply: &str, count: usize, keywords: &[String]) -> Result<Vec<Clue>, String> {
+    let clues = string_list(reply, "clues", count)?;
+    for clue in &clues {
+        let lower = clue.to_lowercase();
+        if let Some(keyword) = keywords
+            .iter()
+            .find(|keyword| lower.contains(&keyword.to_lowercase()))
+        {
+            return Err(format!(
+                "clue \"{clue}\" contains the keyword \"{keyword}\""
+            ));
+        }
+    }
+    Ok(clues.into_iter().map(Clue::Text).collect())
+}
+
+fn parse_code(reply: &str, count: usize, keyword_count: usize) -> Result<Code, String> {
+    let json = reply_json(reply)?;
+    let numbers: Vec<usize> = json
+        .get("code")
+        .and_then(|code| serde_json::from_value(code.clone()).ok())
+        .ok_or_else(|| "expected \"code\" to be a list of numbers".to_owned())?;
+    if numbers.len() != count {
+        return Err(format!("expected {count} numbers, got {}", numbers.len()));
+    }
+    if let Some(number) = numbers.iter().find(|n| !(1..=keyword_count).contains(*n)) {
+        return Err(format!("{number} is not between 1 and {keyword_count}"));
+    }
+    if numbers.iter().collect::<HashSet<_>>().len() != numbers.len() {
+        return Err("the numbers must be different".to_owned());
+    }
+    Ok(Code(numbers.into_iter().map(|n| n - 1).collect()))
+}
+
+fn parse_keywords(reply: &str, count: usize) -> Result<Vec<String>, String> {
+    string_list(reply, "keywords", count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{Json, Router, routing::post};
+
+    use super::*;
+
+    #[test]
+    fn replies_are_parsed() {
+        let keywords = vec!["Sea".to_owned(), "Fire".to_owned()];
+        let clues =
+            parse_clues("Sure! {\"clues\": [\"wave\", \" flame \"]}", 2, &keywords).unwrap();
+        assert!(matches!(&clues[1], Clue::Text(text) if text == "flame"));
+        assert!(parse_clues("{\"clues\": [\"seaside\", \"flame\"]}", 2, &keywords).is_err());
+        assert!(parse_clues("{\"clues\": [\"wave\"]}", 2, &keywords).is_err());
+        assert!(parse_clues("wave, flame", 2, &keywords).is_err());
+
+        assert_eq!(
+            parse_code("{\"code\": [4, 1, 2]}", 3, 4).unwrap(),
+            Code(vec![3, 0, 1])
+        );
+        assert!(parse_code("{\"code\": [0, 1, 2]}", 3, 4).is_err());
+        assert!(parse_code("{\"code\": [1, 1, 2]}", 3, 4).is_err());
+        assert!(parse_code("{\"code\": [\"1\", \"2\", \"3\"]}", 3, 4).is_err());
+
+        assert_eq!(
+            parse_keywords("{\"keywords\": [\"a\", \"b\"]}", 2).unwrap(),
+            ["a", "b"]
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_answers_are_retried() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move |Json(request): Json<Value>| async move {
+                let attempt = counter.fetch_add(1, Ordering::SeqCst);
+                // The earlier invalid answer and the correction are sent back.
+                assert_eq!(
+                    request["messages"].as_array().unwrap().len(),
+                    2 + 2 * attempt
+                );
+                let content = if attempt == 0 {
+                    "{\"code\": [1, 1, 2]}"
+                } else {
+                    "{\"code\": [3, 1, 2]}"
+                };
+                Json(serde_json::json!({
+                    "choices": [{"message": {"role": "assistant", "content": content}}]
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let player = LlmPlayer::new(LlmConfig {
+            url: format!("http://{addr}/v1"),
+            ..LlmConfig::default()
+        })
+        .unwrap();
+        let code = player
+            .ask("Guess".to_owned(), |reply| parse_code(reply, 3, 4))
+            .await;
+        assert_eq!(code, Some(Code(vec![2, 0, 1])));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Giving up after too many invalid answers.
+        let player = LlmPlayer::new(LlmConfig {
+            url: format!("http://{addr}/v1"),
+            max_attempts: 1,
+            ..LlmConfig::default()
+        })
+        .unwrap();
+        requests.store(0, Ordering::SeqCst);
+        let code = player
+            .ask("Guess".to_owned(), |reply| parse_code(reply, 3, 4))
+            .await;
+        assert_eq!(code, None);
+    }
+}
@@ -0,0 +1,522 @@
+use std::{collections::HashSet, fmt, mem, sync::Arc, time::Duration};
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, mpsc};
+
+use crate::{
+    app::State,
+    decrypto::{Code, PerTeam},
+    id::{ConnectionId, UserId},
+    message::{Clue, FromClient, GameStateView, GameView, Inputs, ToClient},
+    metrics,
+    suggest::{clue_history, slot_scores},
+};
+
+pub mod llm;
+pub mod model;
+
+use llm::LlmPlayer;
+use model::AssociationModel;
+
+/// Wait before acting, so the humans can follow what the bot does.
+const THINK_TIME: Duration = Duration::from_secs(2);
+/// Candidate clues considered for each keyword.
+const CLUE_CANDIDATES: usize = 30;
+/// How much a candidate clue is penalized for also fitting one of the other keywords.
+const AMBIGUITY_PENALTY: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BotDifficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl BotDifficulty {
+    /// Number of the best candidate words picked from at random.
+    fn choices(self) -> usize {
+        match self {
+            Self::Easy => 8,
+            Self::Normal => 3,
+            Self::Hard => 1,
+        }
+    }
+
+    /// Largest random amount added to the score of every guess.
+    fn noise(self) -> f32 {
+        match self {
+            Self::Easy => 0.5,
+            Self::Normal => 0.15,
+            Self::Hard => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for BotDifficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Easy => write!(f, "easy"),
+            Self::Normal => write!(f, "normal"),
+            Self::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+/// How a bot picks its clues and guesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum BotKind {
+    /// Word associations from the embeddings model.
+    Association {
+        #[serde(default)]
+        difficulty: BotDifficulty,
+    },
+    /// Asks a language model.
+    Llm,
+}
+
+impl fmt::Display for BotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Association { difficulty } => write!(f, "{difficulty}"),
+            Self::Llm => write!(f, "LLM"),
+        }
+    }
+}
+
+/// A bot that has joined a game, handed over to [`run`] to be played.
+#[derive(Debug)]
+pub struct NewBot {
+    pub connection_id: ConnectionId,
+    pub user_id: UserId,
+    pub kind: BotKind,
+    /// Messages the server sends to the bot.
+    pub outbound: mpsc::UnboundedReceiver<ToClient>,
+}
+
+/// Plays every bot added to a game, until it is removed.
+/// Bots of a kind without a model or LLM player are never added.
+pub async fn run(
+    state: Arc<Mutex<State>>,
+    model: Option<Arc<AssociationModel>>,
+    llm: Option<Arc<LlmPlayer>>,
+    mut new_bots: mpsc::UnboundedReceiver<NewBot>,
+) {
+    while let Some(bot) = new_bots.recv().await {
+        let driver = match bot.kind {
+            BotKind::Association { difficulty } => model.clone().map(|model| Driver::Association {
+                model,
+                difficulty,
+                rng: StdRng::from_os_rng(),
+            }),
+            BotKind::Llm => llm.clone().map(Driver::Llm),
+        };
+        match driver {
+            Some(driver) => {
+                tokio::spawn(run_bot(state.clone(), driver, bot));
+            }
+            None => tracing::error!("Bot {:?} of unavailable kind {}", bot.user_id, bot.kind),
+        }
+    }
+}
+
+enum Driver {
+    Association {
+        model: Arc<AssociationModel>,
+        difficulty: BotDifficulty,
+        rng: StdRng,
+    },
+    Llm(Arc<LlmPlayer>),
+}
+
+impl Driver {
+    async fn decide(&mut self, user_id: UserId, game: &GameView) -> Vec<FromClient> {
+        match self {
+            Self::Association {
+                model,
+                difficulty,
+                rng,
+            } => {
+                let actions = decide(model, *difficulty, user_id, game, rng);
+                if !actions.is_empty() {
+                    // Give the humans a moment to follow.
+                    tokio::time::sleep(THINK_TIME).await;
+                }
+                actions
+            }
+            Self::Llm(llm) => llm.decide(user_id, game).await,
+        }
+    }
+}
+
+/// Point in the game where the bot has to act, so it only acts once on each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Turn {
+    round: usize,
+    inputs: mem::Discriminant<Inputs>,
+}
+
+impl Turn {
+    fn of(game: &GameView) -> Option<Self> {
+        match &game.state {
+            GameStateView::InGame {
+                completed_rounds,
+                inputs,
+                ..
+            } => Some(Self {
+                round: completed_rounds.len(),
+                inputs: mem::discriminant(inputs),
+            }),
+            _ => None,
+        }
+    }
+}
+
+async fn run_bot(state: Arc<Mutex<State>>, mut driver: Driver, mut bot: NewBot) {
+    tracing::debug!("Bot {:?} started", bot.user_id);
+    let mut last_turn = None;
+
+    while let Some(mut msg) = bot.outbound.recv().await {
+        // Only the latest state matters.
+        while let Ok(newer) = bot.outbound.try_recv() {
+            msg = newer;
+        }
+        let game = match msg {
+            ToClient::State {
+                game: Some(game), ..
+            } => game,
+            ToClient::Error { message, .. } => {
+                tracing::debug!("Bot {:?} got an error: {message}", bot.user_id);
+                continue;
+            }
+            _ => continue,
+        };
+
+        let turn = Turn::of(&game);
+        let is_new_turn = turn.is_some() && turn != last_turn;
+        last_turn = turn;
+        if !is_new_turn {
+            continue;
+        }
+
+        // If acting fails, the turn is not retried, and the team times out instead.
+        let actions = driver.decide(bot.user_id, &game).await;
+        if actions.is_empty() {
+            continue;
+        }
+        let mut state = metrics::lock(&state).await;
+        for action in actions {
+            // Errors are sent back to the bot like to any other client.
+            let _ = state.on_message(bot.connection_id, action).await;
+        }
+    }
+    tracing::debug!("Bot {:?} stopped", bot.user_id);
+}
+
+/// Messages the bot sends in reaction to the game state.
+fn decide(
+    model: &AssociationModel,
+    difficulty: BotDifficulty,
+    user_id: UserId,
+    game: &GameView,
+    rng: &mut impl Rng,
+) -> Vec<FromClient> {
+    let GameStateView::InGame {
+        completed_rounds,
+        current_round,
+        keywords,
+        inputs,
+    } = &game.state
+    else {
+        return Vec::new();
+    };
+    let Some(team) = game
+        .players
+        .iter()
+        .find(|p| p.id == user_id)
+        .and_then(|p| p.team)
+    else {
+        return Vec::new();
+    };
+    let history = PerTeam::from_fn(|team| {
+        clue_history(
+            completed_rounds
+                .iter()
+                .map(|round| &round[team].non_computed),
+            game.settings.keyword_count,
+        )
+    });
+
+    match inputs {
+        Inputs::Encrypt { code, .. } => {
+            let used: Vec<_> = history[team].iter().flatten().cloned().collect();
+            vec![FromClient::SubmitClues(give_clues(
+                model, difficulty, keywords, &used, code, rng,
+            ))]
+        }
+        Inputs::Guess {
+            decipher: should_decipher,
+            intercept: should_intercept,
+            ..
+        } => {
+            let Some(round) = current_round else {
+                return Vec::new();
+            };
+            let mut actions = Vec::new();
+            if *should_decipher && let Some(clues) = &round[team].clues {
+                // The team knows its keywords, as well as the earlier clues.
+                let slots: Vec<_> = keywords
+                    .iter()
+                    .zip(&history[team])
+                    .map(|(keyword, earlier)| {
+                        let mut words = earlier.clone();
+                        words.push(keyword.to_lowercase());
+                        words
+                    })
+                    .collect();
+                actions.push(FromClient::SubmitDecipher(guess_code(
+                    model, difficulty, &slots, clues, rng,
+                )));
+            }
+            if *should_intercept && let Some(clues) = &round[team.other()].clues {
+                actions.push(FromClient::SubmitIntercept(guess_code(
+                    model,
+                    difficulty,
+                    &history[team.other()],
+                    clues,
+                    rng,
+                )));
+            }
+            actions
+        }
+        Inputs::Tiebreaker { submitted, .. } => submitted[team]
+            .iter()
+            .enumerate()
+            .filter(|(_, submission)| submission.is_none())
+            .map(|(index, _)| FromClient::SubmitTiebreaker {
+                index,
+                guess: guess_keyword(model, difficulty, &history[team.other()][index], rng),
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Clues for the keywords in the code, avoiding the keywords themselves and clues used before.
+fn give_clues(
+    model: &AssociationModel,
+    difficulty: BotDifficulty,
+    keywords: &[String],
+    used: &[String],
+    code: &Code,
+    rng: &mut impl Rng,
+) -> Vec<Clue> {
+    let keywords_lower: Vec<_> = keywords.iter().map(|k| k.to_lowercase()).collect();
+    let mut chosen: Vec<String> = Vec::new();
+
+    for &index in &code.0 {
+        let exclude = |word: &str| {
+            keywords_lower
+                .iter()
+                .any(|keyword| keyword.contains(word) || word.contains(keyword.as_str()))
+                || used.iter().any(|clue| clue == word)
+                || chosen.iter().any(|clue| clue == word)
+        };
+
+        let mut candidates: Vec<_> = model
+            .neighbors(&keywords[index], CLUE_CANDIDATES, exclude)
+            .into_iter()
+            .map(|(word, score)| {
+                // Clues that also fit another keyword would confuse the team.
+                let ambiguity = keywords
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .filter_map(|(_, keyword)| model.similarity(word, keyword))
+                    .fold(0.0, f32::max);
+                (word, score - AMBIGUITY_PENALTY * ambiguity)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        candidates.truncate(difficulty.choices());
+
+        let clue = match candidates.choose(rng) {
+            Some((word, _)) => word.to_string(),
+            None => {
+                tracing::debug!("Bot knows no clues for {:?}", keywords[index]);
+                model.random_word(rng, exclude).to_owned()
+            }
+        };
+        chosen.push(clue);
+    }
+
+    chosen.into_iter().map(Clue::Text).collect()
+}
+
+/// Guess of a code, from how well each clue fits the words known for each keyword slot.
+fn guess_code(
+    model: &AssociationModel,
+    difficulty: BotDifficulty,
+    slots: &[Vec<String>],
+    clues: &[Clue],
+    rng: &mut impl Rng,
+) -> Code {
+    let mut scores = slot_scores(model, clues, slots);
+    for score in scores.iter_mut().flatten() {
+        *score += noise(difficulty, rng);
+    }
+    assign(&scores)
+}
+
+/// Guess of the other team's keyword, from the clues they gave for it.
+fn guess_keyword(
+    model: &AssociationModel,
+    difficulty: BotDifficulty,
+    history: &[String],
+    rng: &mut impl Rng,
+) -> String {
+    let candidates = model.neighbors(&history.join(" "), difficulty.choices(), |word| {
+        history.iter().any(|clue| clue == word)
+    });
+    match candidates.choose(rng) {
+        Some((word, _)) => word.to_string(),
+        None => model
+            .random_word(rng, |word| history.iter().any(|clue| clue == word))
+            .to_owned(),
+    }
+}
+
+fn noise(difficulty: BotDifficulty, rng: &mut impl Rng) -> f32 {
+    let noise = difficulty.noise();
+    if noise > 0.0 {
+        rng.random_range(0.0..noise)
+    } else {
+        0.0
+    }
+}
+
+/// Picks a different keyword for every clue, taking the best scoring pairs first.
+/// `scores[clue][keyword]` is how well the clue fits the keyword.
+fn assign(scores: &[Vec<f32>]) -> Code {
+    let mut pairs: Vec<_> = scores
+        .iter()
+        .enumerate()
+        .flat_map(|(clue, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(keyword, score)| (clue, keyword, *score))
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let mut code = vec![None; scores.len()];
+    let mut taken = HashSet::new();
+    for (clue, keyword, _) in pairs {
+        if code[clue].is_none() && !taken.contains(&keyword) {
+            code[clue] = Some(keyword);
+            taken.insert(keyword);
+        }
+    }
+    Code(
+        code.into_iter()
+            .map(|keyword| keyword.expect("Should have a keyword for every clue"))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "\
+8 3
+sea 1 0 0
+wave 0.9 0.2 0
+fire 0 1 0
+flame 0.1 0.9 0
+sky 0 0 1
+cloud 0 0.2 0.9
+, 1 1 1
+lion 0.5 0.5 0.5
+";
+
+    fn model() -> AssociationModel {
+        AssociationModel::parse(MODEL.as_bytes()).unwrap()
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn model_is_parsed() {
+        let model = model();
+        assert_eq!(model.len(), 7);
+        assert!(model.similarity("Sea", "wave").unwrap() > 0.9);
+        assert!(model.similarity("sea", "unknown").is_none());
+        assert_eq!(model.neighbors("sea", 1, |w| w == "sea")[0].0, "wave");
+
+        assert!(AssociationModel::parse("sea 1 0\nfire 1\n".as_bytes()).is_err());
+        assert!(AssociationModel::parse("".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn clues_fit_their_keywords() {
+        let model = model();
+        let mut rng = StdRng::seed_from_u64(0);
+        let keywords = words(&["sea", "fire", "sky", "lion"]);
+        let code = Code(vec![2, 0, 1]);
+
+        let clues = give_clues(&model, BotDifficulty::Hard, &keywords, &[], &code, &mut rng);
+        let texts: Vec<_> = clues
+            .iter()
+            .filter_map(|clue| match clue {
+                Clue::Text(text) => Some(text.as_str()),
+                Clue::Drawing(_) | Clue::Strokes(_) => None,
+            })
+            .collect();
+        assert_eq!(texts, ["cloud", "wave", "flame"]);
+
+        let slots: Vec<_> = keywords.iter().map(|k| vec![k.clone()]).collect();
+        let guess = guess_code(&model, BotDifficulty::Hard, &slots, &clues, &mut rng);
+        assert_eq!(guess, code);
+
+        // Used clues are not given again.
+        let clues = give_clues(
+            &model,
+            BotDifficulty::Hard,
+            &keywords,
+            &words(&["wave"]),
+            &Code(vec![0]),
+            &mut rng,
+        );
+        assert!(!matches!(&clues[0], Clue::Text(text) if text == "wave" || text == "sea"));
+    }
+
+    #[test]
+    fn opponents_are_read_from_their_history() {
+        let model = model();
+        let mut rng = StdRng::seed_from_u64(0);
+        let history = vec![
+            words(&["flame"]),
+            Vec::new(),
+            words(&["cloud"]),
+            words(&["wave"]),
+        ];
+        let clues = [
+            Clue::Text("sea".to_owned()),
+            Clue::Text("fire".to_owned()),
+            Clue::Text("sky".to_owned()),
+        ];
+
+        let guess = guess_code(&model, BotDifficulty::Hard, &history, &clues, &mut rng);
+        assert_eq!(guess, Code(vec![3, 0, 2]));
+        assert_eq!(
+            guess_keyword(&model, BotDifficulty::Hard, &history[2], &mut rng),
+            "sky"
+        );
+    }
+}
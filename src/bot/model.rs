@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use rand::{Rng, seq::IndexedRandom};
+
+/// Only this many words are kept from the file.
+/// Embedding files list the most common words first, and rarer ones make poor clues anyway.
+const MAX_WORDS: usize = 100_000;
+/// Random fallback clues are picked from this many of the most common words.
+const COMMON_WORDS: usize = 2_000;
+
+/// Word associations from an embeddings file in the GloVe or word2vec text format:
+/// one word per line followed by its vector, with an optional `<count> <dimensions>` header line.
+/// Words are associated by the cosine similarity of their vectors.
+#[derive(Debug)]
+pub struct AssociationModel {
+    words: Vec<String>,
+    index: HashMap<String, usize>,
+    dimensions: usize,
+    /// Normalized vectors of all words, one after another.
+    vectors: Vec<f32>,
+}
+
+impl AssociationModel {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("Cannot open {}: {e}", path.display()))?;
+        Self::parse(BufReader::new(file)).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(reader: impl BufRead) -> Result<Self, String> {
+        let mut model = Self {
+            words: Vec::new(),
+            index: HashMap::new(),
+            dimensions: 0,
+            vectors: Vec::new(),
+        };
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line.map_err(|e| e.to_string())?;
+            let mut parts = line.split_whitespace();
+            let Some(word) = parts.next() else {
+                continue;
+            };
+            let vector = parts
+                .map(|part| part.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {}: {e}", line_number + 1))?;
+            if line_number == 0 && vector.len() == 1 && word.parse::<usize>().is_ok() {
+                // word2vec header.
+                continue;
+            }
+
+            // Skip punctuation, numbers and the like.
+            let word = word.to_lowercase();
+            if !word.chars().all(char::is_alphabetic) || model.index.contains_key(&word) {
+                continue;
+            }
+
+            if model.dimensions == 0 {
+                model.dimensions = vector.len();
+            }
+            if vector.is_empty() || vector.len() != model.dimensions {
+                return Err(format!(
+                    "line {}: expected {} dimensions, got {}",
+                    line_number + 1,
+                    model.dimensions,
+                    vector.len()
+                ));
+            }
+
+            model.index.insert(word.clone(), model.words.len());
+            model.words.push(word);
+            model.vectors.extend(normalized(vector));
+            if model.words.len() >= MAX_WORDS {
+                break;
+            }
+        }
+
+        if model.words.is_empty() {
+            return Err("no words found".to_owned());
+        }
+        Ok(model)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    fn word_vector(&self, index: usize) -> &[f32] {
+        &self.vectors[index * self.dimensions..(index + 1) * self.dimensions]
+    }
+
+    /// Average vector of the known words in the text, `None` if none are known.
+    fn vector(&self, text: &str) -> Option<Vec<f32>> {
+        let mut sum = vec![0.0; self.dimensions];
+        let mut known = false;
+        for word in text
+            .split(|c: char| !c.is_alphabetic())
+            .filter(|w| !w.is_empty())
+        {
+            if let Some(&index) = self.index.get(&word.to_lowercase()) {
+                known = true;
+                for (s, v) in sum.iter_mut().zip(self.word_vector(index)) {
+                    *s += v;
+                }
+            }
+        }
+        known.then(|| normalized(sum))
+    }
+
+    /// How strongly the texts are associated, from -1 to 1. `None` if either has no known words.
+    pub fn similarity(&self, a: &str, b: &str) -> Option<f32> {
+        Some(dot(&self.vector(a)?, &self.vector(b)?))
+    }
+
+    /// Words most associated with the text, best first, leaving out the excluded ones.
+    pub fn neighbors(
+        &self,
+        text: &str,
+        count: usize,
+        exclude: impl Fn(&str) -> bool,
+    ) -> Vec<(&str, f32)> {
+        let Some(vector) = self.vector(text) else {
+            return Vec::new();
+        };
+
+        let mut scored: Vec<_> = (0..self.words.len())
+            .map(|index| (index, dot(&vector, self.word_vector(index))))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+            .into_iter()
+            .map(|(index, score)| (self.words[index].as_str(), score))
+            .filter(|(word, _)| !exclude(word))
+            .take(count)
+            .collect()
+    }
+
+    /// A common word that is not excluded, for when nothing better is known.
+    pub fn random_word(&self, rng: &mut impl Rng, exclude: impl Fn(&str) -> bool) -> &str {
+        let common: Vec<_> = self.words[..self.words.len().min(COMMON_WORDS)]
+            .iter()
+            .filter(|word| !exclude(word))
+            .collect();
+        common
+            .choose(rng)
+            .map_or(self.words[0].as_str(), |word| word.as_str())
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+fn normalized(mut vector: Vec<f32>) -> Vec<f32> {
+    let length = dot(&vector, &vector).sqrt();
+    if length > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= length);
+    }
+    vector
+}
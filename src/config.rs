@@ -0,0 +1,635 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt, fs,
+    net::ToSocketAddrs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+
+use crate::{decrypto::settings::GameSettings, message::FromClient};
+
+/// Command line options. Each one overrides the matching value in the config file.
+#[derive(Debug, Parser)]
+#[command(version, about = "Decrypto web server")]
+pub struct Cli {
+    /// Address to listen on.
+    #[arg(env = "DECRYPTO_BIND")]
+    pub bind: Option<String>,
+    /// Path to a TOML config file.
+    #[arg(long, short, env = "DECRYPTO_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Directory of the frontend files.
+    #[arg(long, env = "DECRYPTO_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+    /// Directory of the `.txt` wordlists.
+    #[arg(long, env = "DECRYPTO_WORDLIST_DIR")]
+    pub wordlist_dir: Option<PathBuf>,
+    /// Directory for persistent data, such as finished games.
+    #[arg(long, env = "DECRYPTO_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Directory to store drawings in instead of memory.
+    #[arg(long, env = "DECRYPTO_DRAWING_DIR")]
+    pub drawing_dir: Option<PathBuf>,
+    /// Word embeddings file for the bot players, in the GloVe or word2vec text format.
+    #[arg(long, env = "DECRYPTO_BOT_MODEL")]
+    pub bot_model: Option<PathBuf>,
+    /// Base URL of an OpenAI-compatible API for the LLM players, such as `http://localhost:8080/v1`.
+    #[arg(long, env = "DECRYPTO_LLM_URL")]
+    pub llm_url: Option<String>,
+    /// Model name sent to the LLM API.
+    #[arg(long, env = "DECRYPTO_LLM_MODEL")]
+    pub llm_model: Option<String>,
+    /// Bearer token for the LLM API.
+    #[arg(long, env = "DECRYPTO_LLM_API_KEY", hide_env_values = true)]
+    pub llm_api_key: Option<Secret>,
+    /// Bearer token for the admin API under `/admin`.
+    #[arg(long, env = "DECRYPTO_ADMIN_TOKEN", hide_env_values = true)]
+    pub admin_token: Option<Secret>,
+    #[arg(long, env = "DECRYPTO_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+    /// Maximum size of an uploaded drawing, in bytes.
+    #[arg(long, env = "DECRYPTO_MAX_DRAWING_BYTES")]
+    pub max_drawing_bytes: Option<usize>,
+    /// Maximum size of a chat message, in bytes.
+    #[arg(long, env = "DECRYPTO_MAX_CHAT_MESSAGE_BYTES")]
+    pub max_chat_message_bytes: Option<usize>,
+    /// Maximum number of lobbies at once.
+    #[arg(long, env = "DECRYPTO_MAX_LOBBIES")]
+    pub max_lobbies: Option<usize>,
+    /// Maximum number of users at once.
+    #[arg(long, env = "DECRYPTO_MAX_USERS")]
+    pub max_users: Option<usize>,
+    /// Nickname length bounds, in bytes.
+    #[arg(long, env = "DECRYPTO_MIN_NICK_LEN")]
+    pub min_nick_len: Option<usize>,
+    #[arg(long, env = "DECRYPTO_MAX_NICK_LEN")]
+    pub max_nick_len: Option<usize>,
+    /// Settings for newly created lobbies, as JSON. Fields left out have their built-in defaults.
+    #[arg(long, env = "DECRYPTO_DEFAULT_SETTINGS", value_parser = parse_settings)]
+    pub default_settings: Option<GameSettings>,
+}
+
+fn parse_settings(json: &str) -> Result<GameSettings, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+/// Password or token from the configuration.
+/// Redacted when debug printed, so it never ends up in the logs.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Server configuration.
+/// Loaded from the config file, with command line options and environment variables on top.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct Config {
+    /// Address to listen on.
+    pub bind: String,
+    /// Directory of the frontend files.
+    pub static_dir: PathBuf,
+    /// Directory of the `.txt` wordlists.
+    pub wordlist_dir: PathBuf,
+    /// How often `wordlist_dir` is polled for changed files, in seconds.
+    pub wordlist_poll_secs: u64,
+    /// Directory for persistent data, such as finished games. Created if missing.
+    pub data_dir: PathBuf,
+    /// Directory to store drawings in instead of memory.
+    /// Leftover `.drawing` files in it are removed on startup, so it must not be shared.
+    pub drawing_dir: Option<PathBuf>,
+    /// Word embeddings file for the bot players, in the GloVe or word2vec text format.
+    /// Bots are not available if `None`.
+    pub bot_model: Option<PathBuf>,
+    /// API for the LLM players. They are not available if `None`.
+    pub llm: Option<LlmConfig>,
+    /// Bearer token for the admin API under `/admin`. The API is disabled if `None`.
+    pub admin_token: Option<Secret>,
+    pub log_format: LogFormat,
+    pub limits: Limits,
+    pub rate_limits: RateLimits,
+    pub heartbeat: Heartbeat,
+    pub shutdown: Shutdown,
+    /// Settings for newly created lobbies.
+    pub default_settings: GameSettings,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:3000".to_owned(),
+            static_dir: PathBuf::from("./static"),
+            wordlist_dir: PathBuf::from("./wordlists"),
+            wordlist_poll_secs: 5,
+            data_dir: PathBuf::from("./data"),
+            drawing_dir: None,
+            bot_model: None,
+            llm: None,
+            admin_token: None,
+            log_format: LogFormat::default(),
+            limits: Limits::default(),
+            rate_limits: RateLimits::default(),
+            heartbeat: Heartbeat::default(),
+            shutdown: Shutdown::default(),
+            default_settings: GameSettings::default(),
+        }
+    }
+}
+
+/// OpenAI-compatible chat completions API, used by the LLM players.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct LlmConfig {
+    /// Base URL of the API, `/chat/completions` is appended to it.
+    pub url: String,
+    pub model: String,
+    /// Sent as a bearer token, if set.
+    pub api_key: Option<Secret>,
+    /// Maximum length of a reply, in tokens.
+    pub max_tokens: u32,
+    pub temperature: f32,
+    /// Requests for a single move before giving up, if the replies are invalid.
+    pub max_attempts: u32,
+    /// Timeout of a single request, in seconds.
+    pub timeout_secs: u64,
+}
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            url: "http://localhost:8080/v1".to_owned(),
+            model: "default".to_owned(),
+            api_key: None,
+            max_tokens: 200,
+            temperature: 0.7,
+            max_attempts: 3,
+            timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct Limits {
+    /// Maximum size of an uploaded drawing, in bytes.
+    pub max_drawing_bytes: usize,
+    /// Maximum dimensions of an uploaded drawing, in pixels.
+    pub max_drawing_width: u32,
+    pub max_drawing_height: u32,
+    /// Maximum number of drawings kept for a game, in total and per uploader.
+    pub max_drawings_per_game: usize,
+    pub max_drawings_per_user: usize,
+    /// Drawings not used as a clue are removed this long after being uploaded, in seconds.
+    pub unused_drawing_timeout_secs: u64,
+    /// Codes for moving a session to another device can be used for this long, in seconds.
+    pub pairing_code_ttl_secs: u64,
+    /// Maximum size of a chat message, in bytes.
+    pub max_chat_message_bytes: usize,
+    /// Nickname length bounds, in bytes.
+    pub min_nick_len: usize,
+    pub max_nick_len: usize,
+    /// Maximum number of lobbies at once. No limit if `None`.
+    pub max_lobbies: Option<usize>,
+    /// Maximum number of users at once. No limit if `None`.
+    pub max_users: Option<usize>,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_drawing_bytes: 1024 * 1024,
+            max_drawing_width: 1600,
+            max_drawing_height: 1200,
+            max_drawings_per_game: 200,
+            max_drawings_per_user: 20,
+            unused_drawing_timeout_secs: 600,
+            pairing_code_ttl_secs: 300,
+            max_chat_message_bytes: 4095,
+            min_nick_len: 2,
+            max_nick_len: 64,
+            max_lobbies: None,
+            max_users: None,
+        }
+    }
+}
+
+/// Pings to detect websocket clients that went away without closing the connection.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct Heartbeat {
+    /// Time between pings, in seconds.
+    pub interval_secs: u64,
+    /// Connections that sent nothing, not even a pong, for this long are closed. In seconds.
+    pub timeout_secs: u64,
+}
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval_secs: 15,
+            timeout_secs: 45,
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// What happens on SIGTERM or SIGINT.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct Shutdown {
+    /// How long the server is expected to be down, told to the clients. In seconds.
+    /// Clients are not told a time if `None`.
+    pub expected_downtime_secs: Option<u64>,
+    /// Connections still open this long after the clients were told are dropped. In seconds.
+    pub close_timeout_secs: u64,
+}
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            expected_downtime_secs: None,
+            close_timeout_secs: 10,
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn close_timeout(&self) -> Duration {
+        Duration::from_secs(self.close_timeout_secs)
+    }
+}
+
+/// Token bucket limits on what clients send, applied per connection and per user.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "snake_case")]
+pub struct RateLimits {
+    /// Limit for each message type that has no limit of its own in `messages`.
+    pub default: RateLimit,
+    /// Limits by message type, such as `global_chat`.
+    pub messages: HashMap<String, RateLimit>,
+    /// Limit on uploading drawings, per user.
+    pub drawing_upload: RateLimit,
+    /// Messages dropped within `violation_window_secs` before the client is disconnected.
+    pub max_violations: u32,
+    pub violation_window_secs: u64,
+}
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            default: RateLimit::new(20, 5.0),
+            messages: HashMap::from([
+                ("global_chat".to_owned(), RateLimit::new(5, 1.0)),
+                ("frustrated".to_owned(), RateLimit::new(3, 0.2)),
+                ("set_nick".to_owned(), RateLimit::new(3, 0.1)),
+                ("create_lobby".to_owned(), RateLimit::new(3, 0.1)),
+                ("add_bot".to_owned(), RateLimit::new(8, 0.5)),
+                ("set_custom_wordlist".to_owned(), RateLimit::new(3, 0.1)),
+                ("request_suggestions".to_owned(), RateLimit::new(5, 0.5)),
+                ("request_pairing_code".to_owned(), RateLimit::new(3, 0.1)),
+                ("redeem_pairing_code".to_owned(), RateLimit::new(5, 0.1)),
+            ]),
+            drawing_upload: RateLimit::new(5, 0.5),
+            max_violations: 20,
+            violation_window_secs: 60,
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn for_message(&self, kind: &str) -> RateLimit {
+        self.messages.get(kind).copied().unwrap_or(self.default)
+    }
+}
+
+/// Allows `burst` actions at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+impl RateLimit {
+    pub const fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+}
+
+impl Config {
+    /// File of the persisted user identities.
+    pub fn users_path(&self) -> PathBuf {
+        self.data_dir.join("users.json")
+    }
+
+    /// Directory of the archived games.
+    pub fn archive_dir(&self) -> PathBuf {
+        self.data_dir.join("games")
+    }
+
+    /// Builds the configuration from the command line, environment and config file.
+    pub fn load() -> Result<Self, String> {
+        Self::from_cli(Cli::parse())
+    }
+
+    pub fn from_cli(cli: Cli) -> Result<Self, String> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+
+        if let Some(bind) = cli.bind {
+            config.bind = bind;
+        }
+        if let Some(static_dir) = cli.static_dir {
+            config.static_dir = static_dir;
+        }
+        if let Some(wordlist_dir) = cli.wordlist_dir {
+            config.wordlist_dir = wordlist_dir;
+        }
+        if let Some(data_dir) = cli.data_dir {
+            config.data_dir = data_dir;
+        }
+        if cli.drawing_dir.is_some() {
+            config.drawing_dir = cli.drawing_dir;
+        }
+        if cli.bot_model.is_some() {
+            config.bot_model = cli.bot_model;
+        }
+        if cli.llm_url.is_some() || cli.llm_model.is_some() || cli.llm_api_key.is_some() {
+            let llm = config.llm.get_or_insert_default();
+            if let Some(url) = cli.llm_url {
+                llm.url = url;
+            }
+            if let Some(model) = cli.llm_model {
+                llm.model = model;
+            }
+            if cli.llm_api_key.is_some() {
+                llm.api_key = cli.llm_api_key;
+            }
+        }
+        if cli.admin_token.is_some() {
+            config.admin_token = cli.admin_token;
+        }
+        if let Some(log_format) = cli.log_format {
+            config.log_format = log_format;
+        }
+        if let Some(max_drawing_bytes) = cli.max_drawing_bytes {
+            config.limits.max_drawing_bytes = max_drawing_bytes;
+        }
+        if let Some(max_chat_message_bytes) = cli.max_chat_message_bytes {
+            config.limits.max_chat_message_bytes = max_chat_message_bytes;
+        }
+        if cli.max_lobbies.is_some() {
+            config.limits.max_lobbies = cli.max_lobbies;
+        }
+        if cli.max_users.is_some() {
+            config.limits.max_users = cli.max_users;
+        }
+        if let Some(min_nick_len) = cli.min_nick_len {
+            config.limits.min_nick_len = min_nick_len;
+        }
+        if let Some(max_nick_len) = cli.max_nick_len {
+            config.limits.max_nick_len = max_nick_len;
+        }
+        if let Some(default_settings) = cli.default_settings {
+            config.default_settings = default_settings;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read config file {}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid config file {}: {e}", path.display()))
+    }
+
+    /// Checks everything that doesn't need the wordlists to be loaded.
+    /// `default_settings` is validated separately once they are.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut addrs = self
+            .bind
+            .to_socket_addrs()
+            .map_err(|e| format!("Invalid bind address '{}': {e}", self.bind))?;
+        if addrs.next().is_none() {
+            return Err(format!("Bind address '{}' did not resolve", self.bind));
+        }
+
+        for (name, dir) in [
+            ("static_dir", Some(&self.static_dir)),
+            ("wordlist_dir", Some(&self.wordlist_dir)),
+            ("drawing_dir", self.drawing_dir.as_ref()),
+        ] {
+            let Some(dir) = dir else {
+                continue;
+            };
+            if !dir.is_dir() {
+                return Err(format!("{name} '{}' is not a directory", dir.display()));
+            }
+        }
+
+        if self.wordlist_poll_secs == 0 {
+            return Err("wordlist_poll_secs must be positive".to_owned());
+        }
+
+        if let Some(bot_model) = &self.bot_model
+            && !bot_model.is_file()
+        {
+            return Err(format!("bot_model '{}' is not a file", bot_model.display()));
+        }
+
+        if let Some(llm) = &self.llm {
+            if !llm.url.starts_with("http://") && !llm.url.starts_with("https://") {
+                return Err(format!("llm.url '{}' must be an http(s) URL", llm.url));
+            }
+            if llm.max_tokens == 0 || llm.max_attempts == 0 || llm.timeout_secs == 0 {
+                return Err(
+                    "llm.max_tokens, llm.max_attempts and llm.timeout_secs must be positive"
+                        .to_owned(),
+                );
+            }
+        }
+
+        if self
+            .admin_token
+            .as_ref()
+            .is_some_and(|token| token.expose().trim().is_empty())
+        {
+            return Err("admin_token must not be empty".to_owned());
+        }
+
+        if self.data_dir.exists() && !self.data_dir.is_dir() {
+            return Err(format!(
+                "data_dir '{}' is not a directory",
+                self.data_dir.display()
+            ));
+        }
+
+        let limits = &self.limits;
+        if limits.max_drawing_bytes == 0 {
+            return Err("max_drawing_bytes must be positive".to_owned());
+        }
+        if limits.max_drawing_width == 0 || limits.max_drawing_height == 0 {
+            return Err("max_drawing_width and max_drawing_height must be positive".to_owned());
+        }
+        if limits.max_drawings_per_game == 0 || limits.max_drawings_per_user == 0 {
+            return Err(
+                "max_drawings_per_game and max_drawings_per_user must be positive".to_owned(),
+            );
+        }
+        if limits.unused_drawing_timeout_secs == 0 {
+            return Err("unused_drawing_timeout_secs must be positive".to_owned());
+        }
+        if limits.pairing_code_ttl_secs == 0 {
+            return Err("pairing_code_ttl_secs must be positive".to_owned());
+        }
+        if limits.max_chat_message_bytes == 0 {
+            return Err("max_chat_message_bytes must be positive".to_owned());
+        }
+        if limits.min_nick_len == 0 || limits.min_nick_len > limits.max_nick_len {
+            return Err(format!(
+                "Nickname bounds must satisfy 0 < min_nick_len <= max_nick_len, got {} and {}",
+                limits.min_nick_len, limits.max_nick_len
+            ));
+        }
+        if limits.max_lobbies == Some(0) {
+            return Err("max_lobbies must be positive".to_owned());
+        }
+        if limits.max_users == Some(0) {
+            return Err("max_users must be positive".to_owned());
+        }
+
+        let heartbeat = &self.heartbeat;
+        if heartbeat.interval_secs == 0 || heartbeat.timeout_secs <= heartbeat.interval_secs {
+            return Err(format!(
+                "Heartbeat must satisfy 0 < interval_secs < timeout_secs, got {} and {}",
+                heartbeat.interval_secs, heartbeat.timeout_secs
+            ));
+        }
+
+        let rate_limits = &self.rate_limits;
+        for kind in rate_limits.messages.keys() {
+            if !FromClient::KINDS.contains(&kind.as_str()) {
+                return Err(format!(
+                    "Unknown message type '{kind}' in rate_limits.messages"
+                ));
+            }
+        }
+        for (name, limit) in [
+            ("rate_limits.default", &rate_limits.default),
+            ("rate_limits.drawing_upload", &rate_limits.drawing_upload),
+        ]
+        .into_iter()
+        .chain(
+            rate_limits
+                .messages
+                .iter()
+                .map(|(kind, limit)| (kind.as_str(), limit)),
+        ) {
+            if limit.burst == 0 || !limit.per_second.is_finite() || limit.per_second <= 0.0 {
+                return Err(format!("{name} needs a positive burst and per_second"));
+            }
+        }
+        if rate_limits.max_violations == 0 || rate_limits.violation_window_secs == 0 {
+            return Err(
+                "rate_limits.max_violations and rate_limits.violation_window_secs must be positive"
+                    .to_owned(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_config_is_valid() {
+        let config = Config::from_file(Path::new("decrypto.example.toml")).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn cli_overrides_config_file() {
+        let cli = Cli::parse_from([
+            "decrypto-web",
+            "127.0.0.1:1234",
+            "--config",
+            "decrypto.example.toml",
+            "--max-users",
+            "5",
+            "--max-nick-len",
+            "20",
+            "--default-settings",
+            r#"{"keyword_count": 5}"#,
+        ]);
+        let config = Config::from_cli(cli).unwrap();
+        assert_eq!(config.bind, "127.0.0.1:1234");
+        assert_eq!(config.limits.max_users, Some(5));
+        assert_eq!(config.limits.max_nick_len, 20);
+        assert_eq!(config.default_settings.keyword_count, 5);
+        assert_eq!(config.default_settings.clue_count, 3);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("bnid = \"0.0.0.0:3000\"").is_err());
+        assert!(toml::from_str::<Config>("[limits]\nmax_user = 1").is_err());
+    }
+
+    #[test]
+    fn secrets_are_not_debug_printed() {
+        let cli = Cli::parse_from(["decrypto-web", "--admin-token", "hunter2"]);
+        let config = Config::from_cli(cli).unwrap();
+        assert_eq!(config.admin_token.as_ref().unwrap().expose(), "hunter2");
+        assert!(!format!("{config:?}").contains("hunter2"));
+    }
+
+    #[test]
+    fn invalid_limits_are_rejected() {
+        let mut config = Config::default();
+        config.limits.min_nick_len = 10;
+        config.limits.max_nick_len = 5;
+        assert!(config.validate().is_err());
+    }
+}
@@ -2,7 +2,8 @@ use std::{
     collections::HashMap,
     fmt,
     ops::{Index, IndexMut},
-    time::Instant,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
     vec,
 };
 
@@ -10,16 +11,31 @@ use rand::{Rng, seq::IndexedRandom};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    decrypto::settings::GameSettings,
-    id::{DrawingId, UserId},
+    decrypto::{
+        registry::WordlistRegistry,
+        settings::GameSettings,
+        wordlist::{Language, Wordlist},
+    },
+    drawing::Drawing,
+    id::{DrawingId, RecordId, UserId},
     message::{ChatMessage, Clue, CurrentRoundPerTeam, Deadline, DeadlineReason},
 };
 
 mod code;
+pub mod record;
+pub mod registry;
 pub mod settings;
+pub mod wordlist;
 
 pub use code::Code;
 
+/// Current time as a Unix timestamp, in seconds.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
 /// Global game state
 pub struct GameState {}
 
@@ -80,20 +96,46 @@ impl fmt::Display for RoundResult {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub struct GameInfo {
     /// Settings for the game.
     pub settings: GameSettings,
     pub global_chat: Vec<ChatMessage>,
     ///  All players that have ever been in this game.
     players: HashMap<UserId, GamePlayerInfo>,
-    /// Drawings in this game lobby, in png format.
-    pub drawings: HashMap<DrawingId, Vec<u8>>,
+    /// Drawings uploaded to this game.
+    pub drawings: HashMap<DrawingId, Drawing>,
+    /// Keyword list uploaded to this lobby, selected with the `"custom"` wordlist id.
+    #[serde(skip)]
+    pub custom_wordlist: Option<Arc<Wordlist>>,
+    /// Language of the keywords in play, set when the game starts.
+    pub language: Language,
+    /// Archived record of the game, once it has finished and been archived.
+    pub record_id: Option<RecordId>,
+    /// Record of the game being written to the archive, until `record_id` is set.
+    #[serde(skip)]
+    pub archiving: Option<RecordId>,
+    /// Keywords used in earlier games in this lobby, in the order they were used.
+    /// They are not picked again until the wordlists run out.
+    pub keyword_history: Vec<String>,
+    /// When the current or last game was started.
+    #[serde(skip)]
+    pub started_at: Option<Instant>,
+    /// When the last game ended, as a Unix timestamp in seconds. `None` while playing.
+    pub finished_at: Option<u64>,
     /// State of the game.
     pub state: GameInfoState,
 }
 
 impl GameInfo {
+    pub fn new(settings: GameSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
     pub fn add_player(&mut self, user_id: UserId) {
         self.players.insert(user_id, GamePlayerInfo::default());
     }
@@ -117,7 +159,7 @@ impl GameInfo {
         &mut self.players
     }
 
-    pub fn startable(&self) -> Result<(), String> {
+    pub fn startable(&self, wordlists: &WordlistRegistry) -> Result<(), String> {
         if !matches!(self.state, GameInfoState::Lobby) {
             return Err("Only games in lobby can be started".to_owned());
         }
@@ -142,7 +184,8 @@ impl GameInfo {
             }
         }
 
-        self.settings.validate()
+        self.settings
+            .validate(wordlists, self.custom_wordlist.as_ref())
     }
 
     pub fn players_in_team(&self, team: Team) -> Vec<UserId> {
@@ -158,17 +201,58 @@ impl GameInfo {
             .collect()
     }
 
+    /// Drawings are used as clues, so only the current encryptors can upload them.
+    pub fn check_drawing_upload(&self, user_id: UserId) -> Result<(), String> {
+        let Some(GamePlayerInfo::InTeam(team)) = self.players.get(&user_id) else {
+            return Err("You are not in a team in this game".to_owned());
+        };
+
+        let GameInfoState::InGame {
+            current_round: GameInfoStateCurrentRound::Normal(round),
+            ..
+        } = &self.state
+        else {
+            return Err("Drawings can only be uploaded during a round".to_owned());
+        };
+
+        if round[*team].encryptor != user_id {
+            return Err("Only the encryptor can upload drawings".to_owned());
+        }
+
+        Ok(())
+    }
+
     pub fn team_for_user(&self, user_id: UserId) -> Option<Team> {
         self.players
             .get(&user_id)
             .and_then(|info| info.access_to_info())
     }
 
-    pub fn start(&mut self) {
-        debug_assert!(self.startable().is_ok());
+    pub fn start(&mut self, wordlists: &WordlistRegistry) -> Result<(), String> {
+        self.startable(wordlists)?;
+
+        let wordlists = self
+            .settings
+            .load_wordlists(wordlists, self.custom_wordlist.as_ref())?;
+        let keywords = match self
+            .settings
+            .pick_random_keywords(&wordlists, &self.keyword_history)
+        {
+            Ok(keywords) => keywords,
+            Err(_) => {
+                // Not enough unused keywords left, start over.
+                self.keyword_history.clear();
+                self.settings.pick_random_keywords(&wordlists, &[])?
+            }
+        };
+        self.keyword_history
+            .extend(keywords.0.iter().flatten().cloned());
+        self.language = wordlists[0].info.language.clone();
+        self.started_at = Some(Instant::now());
+        self.finished_at = None;
 
         self.state = GameInfoState::InGame {
-            keywords: self.settings.pick_random_keywords(),
+            keywords,
             completed_rounds: Vec::new(),
             current_round: GameInfoStateCurrentRound::Normal(Round::from(Team::ORDER.map(
                 |team| {
@@ -183,6 +267,8 @@ impl GameInfo {
                         decipher: None,
                         intercept: None,
                         timed_out: TimedOut::default(),
+                        started_at: Some(Instant::now()),
+                        encrypt_millis: None,
                     }
                 },
             ))),
@@ -193,6 +279,46 @@ impl GameInfo {
                 })
             }),
         };
+        Ok(())
+    }
+
+    /// Move a finished game back to the lobby, so the same players can start a new one.
+    /// Teams and settings are kept. Drawings are dropped, so that they don't count toward
+    /// the quotas of the next game; the archived record has copies of the ones used as clues.
+    pub fn return_to_lobby(&mut self) -> Result<(), String> {
+        if !matches!(self.state, GameInfoState::GameOver { .. }) {
+            return Err("Only finished games can return to the lobby".to_owned());
+        }
+        self.state = GameInfoState::Lobby;
+        self.record_id = None;
+        self.archiving = None;
+        self.drawings.clear();
+        Ok(())
+    }
+
+    /// End a game in progress without a winner, keeping the rounds played so far.
+    /// The game is not archived, as it was not played to the end.
+    pub fn force_end(&mut self) -> Result<(), String> {
+        let GameInfoState::InGame {
+            keywords,
+            completed_rounds,
+            current_round,
+            ..
+        } = std::mem::take(&mut self.state)
+        else {
+            return Err("Game is not in progress".to_owned());
+        };
+        self.state = GameInfoState::GameOver {
+            winner: None,
+            keywords,
+            completed_rounds,
+            tiebreaker: match current_round {
+                GameInfoStateCurrentRound::Tiebreaker(tiebreaker) => Some(tiebreaker),
+                GameInfoStateCurrentRound::Normal(_) => None,
+            },
+        };
+        self.finished_at = Some(unix_now());
+        Ok(())
     }
 
     #[must_use]
@@ -263,6 +389,7 @@ impl GameInfo {
                 completed_rounds: completed_rounds.clone(),
                 tiebreaker: None,
             };
+            self.finished_at = Some(unix_now());
             return Some(score);
         }
 
@@ -293,6 +420,8 @@ impl GameInfo {
                 decipher: None,
                 intercept: None,
                 timed_out: TimedOut::default(),
+                started_at: Some(Instant::now()),
+                encrypt_millis: None,
             }
         })));
 
@@ -364,7 +493,7 @@ impl GameInfo {
                 .filter(|(guess, correct)| {
                     guess
                         .as_ref()
-                        .map(|guess| check_tiebreaker_guess(guess, correct))
+                        .map(|guess| check_tiebreaker_guess(guess, correct, &self.language))
                         .unwrap_or(false)
                 })
                 .count()
@@ -382,6 +511,7 @@ impl GameInfo {
             completed_rounds: completed_rounds.clone(),
             tiebreaker: Some(tiebreaker.clone()),
         };
+        self.finished_at = Some(unix_now());
 
         Some(scores.into())
     }
@@ -413,7 +543,8 @@ impl GamePlayerInfo {
 }
 
 /// State-specific information about the game.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum GameInfoState {
     #[default]
     Lobby,
@@ -434,7 +565,21 @@ pub enum GameInfoState {
     },
 }
 
-#[derive(Debug, Clone)]
+impl GameInfoState {
+    pub const NAMES: [&str; 3] = ["lobby", "in_game", "game_over"];
+
+    /// Name of the state, one of [`Self::NAMES`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Lobby => "lobby",
+            Self::InGame { .. } => "in_game",
+            Self::GameOver { .. } => "game_over",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum GameInfoStateCurrentRound {
     Normal(Round),
     Tiebreaker(TiebreakerRound),
@@ -580,6 +725,12 @@ pub struct RoundPerTeam {
     /// `None` if the team ran out of time, or has not intercepted yet.
     pub intercept: Option<Code>,
     pub timed_out: TimedOut,
+    /// When the round started, for timing the encryptor.
+    #[serde(skip)]
+    pub started_at: Option<Instant>,
+    /// How long the encryptor took to give clues, in milliseconds.
+    #[serde(default)]
+    pub encrypt_millis: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
@@ -608,6 +759,6 @@ pub struct TiebreakerRoundPerTeam {
     pub timed_out: Option<DeadlineReason>,
 }
 
-pub fn check_tiebreaker_guess(guess: &str, correct: &str) -> bool {
-    guess.trim().eq_ignore_ascii_case(correct.trim())
+pub fn check_tiebreaker_guess(guess: &str, correct: &str, language: &Language) -> bool {
+    language.matches(guess, correct)
 }
@@ -0,0 +1,522 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    decrypto::{
+        GameInfo, GameInfoState, PerTeam, Team, TiebreakerRound, check_tiebreaker_guess,
+        settings::GameSettings, unix_now, wordlist::Language,
+    },
+    id::{DrawingId, GameId, RecordId, UserId},
+    message::{Clue, CompletedRoundPerTeam},
+};
+
+/// Self-contained record of a finished game, with everything needed to show it later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GameRecord {
+    pub id: RecordId,
+    /// Lobby the game was played in.
+    pub game_id: GameId,
+    /// Unix timestamp, in seconds.
+    pub finished_at: u64,
+    pub settings: GameSettings,
+    pub language: Language,
+    pub players: Vec<RecordPlayer>,
+    pub winner: Option<Team>,
+    pub keywords: PerTeam<Vec<String>>,
+    pub rounds: Vec<PerTeam<CompletedRoundPerTeam>>,
+    pub tiebreaker: Option<TiebreakerRound>,
+    /// Drawings used as clues, as data URLs.
+    pub drawings: HashMap<DrawingId, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RecordPlayer {
+    pub id: UserId,
+    pub nick: String,
+    /// Team the player ended the game in, if any.
+    pub team: Option<Team>,
+    /// Bots are shown in the record, but not rated nor counted in the stats.
+    #[serde(default)]
+    pub is_bot: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
+impl GameRecord {
+    /// Records a finished game. Drawings are left empty, as loading them may need IO.
+    pub fn new(
+        game_id: GameId,
+        game: &GameInfo,
+        nick: impl Fn(UserId) -> String,
+        is_bot: impl Fn(UserId) -> bool,
+    ) -> Result<Self, String> {
+        let GameInfoState::GameOver {
+            winner,
+            keywords,
+            completed_rounds,
+            tiebreaker,
+        } = &game.state
+        else {
+            return Err("Only finished games can be exported".to_owned());
+        };
+
+        let mut players: Vec<_> = game
+            .players()
+            .iter()
+            .map(|(id, info)| RecordPlayer {
+                id: *id,
+                nick: nick(*id),
+                team: info.access_to_info(),
+                is_bot: is_bot(*id),
+            })
+            .collect();
+        players.sort_by(|a, b| (a.team, &a.nick).cmp(&(b.team, &b.nick)));
+
+        Ok(Self {
+            id: RecordId::new(),
+            game_id,
+            // Set when the game ended, so that exporting again gives the same record.
+            finished_at: game.finished_at.unwrap_or_else(unix_now),
+            settings: game.settings.clone(),
+            language: game.language.clone(),
+            players,
+            winner: *winner,
+            keywords: keywords.clone(),
+            rounds: completed_rounds
+                .iter()
+                .map(|round| {
+                    let score = round.score();
+                    PerTeam::from_fn(|t| CompletedRoundPerTeam {
+                        non_computed: round[t].clone(),
+                        score: score[t],
+                    })
+                })
+                .collect(),
+            tiebreaker: tiebreaker.clone(),
+            drawings: HashMap::new(),
+        })
+    }
+
+    /// Drawings used as clues, in the order they were given.
+    pub fn drawing_ids(&self) -> Vec<DrawingId> {
+        self.rounds
+            .iter()
+            .flat_map(|round| round.0.iter())
+            .flat_map(|team| team.non_computed.clues.iter().flatten())
+            .filter_map(|clue| clue.drawing().map(|(id, _)| id))
+            .collect()
+    }
+
+    pub fn has_player(&self, user_id: UserId) -> bool {
+        self.players.iter().any(|p| p.id == user_id)
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Json => serde_json::to_string_pretty(self).expect("Should serialize"),
+            ExportFormat::Markdown => self.to_markdown(),
+            ExportFormat::Html => self.to_html(),
+        }
+    }
+
+    fn nick(&self, user_id: UserId) -> &str {
+        self.players
+            .iter()
+            .find(|p| p.id == user_id)
+            .map_or("?", |p| &p.nick)
+    }
+
+    fn finished_at_text(&self) -> String {
+        let time = UNIX_EPOCH + Duration::from_secs(self.finished_at);
+        humantime::format_rfc3339_seconds(time).to_string()
+    }
+
+    fn winner_text(&self) -> String {
+        match self.winner {
+            Some(team) => format!("{} team won", team_name(team)),
+            None => "Draw".to_owned(),
+        }
+    }
+
+    /// Clues given for each keyword of the team, over all rounds.
+    fn clues_per_keyword(&self, team: Team) -> Vec<Vec<&Clue>> {
+        let mut per_keyword = vec![Vec::new(); self.keywords[team].len()];
+        for round in &self.rounds {
+            let round = &round[team].non_computed;
+            for (index, clue) in round.code.0.iter().zip(round.clues.iter().flatten()) {
+                if let Some(clues) = per_keyword.get_mut(*index) {
+                    clues.push(clue);
+                }
+            }
+        }
+        per_keyword
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        let clue = |clue: &Clue| match clue {
+            Clue::Text(text) => escape_markdown(text),
+            Clue::Drawing(id) | Clue::Strokes(id) => match self.drawings.get(id) {
+                Some(url) => format!("![drawing]({url})"),
+                None => "*drawing*".to_owned(),
+            },
+        };
+
+        let _ = writeln!(md, "# Decrypto game {}\n", self.game_id);
+        let _ = writeln!(
+            md,
+            "Finished {}. **{}.**\n",
+            self.finished_at_text(),
+            self.winner_text()
+        );
+
+        for team in Team::ORDER {
+            let _ = writeln!(md, "## {} team\n", team_name(team));
+            let players: Vec<_> = self
+                .players
+                .iter()
+                .filter(|p| p.team == Some(team))
+                .map(|p| escape_markdown(&p.nick))
+                .collect();
+            let _ = writeln!(md, "Players: {}\n", players.join(", "));
+            let _ = writeln!(md, "| # | Keyword | Clues |\n|---|---|---|");
+            for (i, (keyword, clues)) in self.keywords[team]
+                .iter()
+                .zip(self.clues_per_keyword(team))
+                .enumerate()
+            {
+                let clues: Vec<_> = clues.into_iter().map(clue).collect();
+                let _ = writeln!(
+                    md,
+                    "| {} | {} | {} |",
+                    i + 1,
+                    escape_markdown(keyword),
+                    clues.join(", ")
+                );
+            }
+            md.push('\n');
+        }
+
+        let _ = writeln!(md, "## Rounds\n");
+        for (i, round) in self.rounds.iter().enumerate() {
+            let _ = writeln!(md, "### Round {}\n", i + 1);
+            for team in Team::ORDER {
+                let r = &round[team];
+                let clues: Vec<_> = r.non_computed.clues.iter().flatten().map(clue).collect();
+                let _ = writeln!(
+                    md,
+                    "- **{}**: {} encrypted {} as {}. {}",
+                    team_name(team),
+                    escape_markdown(self.nick(r.non_computed.encryptor)),
+                    r.non_computed.code,
+                    if clues.is_empty() {
+                        "nothing".to_owned()
+                    } else {
+                        clues.join(", ")
+                    },
+                    self.guess_text(round, team),
+                );
+            }
+            md.push('\n');
+        }
+
+        if let Some(tiebreaker) = &self.tiebreaker {
+            let _ = writeln!(md, "## Tiebreaker\n");
+            for team in Team::ORDER {
+                let _ = writeln!(
+                    md,
+                    "- **{}** guessed {}",
+                    team_name(team),
+                    escape_markdown(&self.tiebreaker_text(tiebreaker, team))
+                );
+            }
+        }
+
+        md
+    }
+
+    fn to_html(&self) -> String {
+        let mut html = String::new();
+        let clue = |clue: &Clue| match clue {
+            Clue::Text(text) => escape_html(text),
+            Clue::Drawing(id) | Clue::Strokes(id) => match self.drawings.get(id) {
+                Some(url) => format!(r#"<img src="{}" alt="drawing">"#, escape_html(url)),
+                None => "<em>drawing</em>".to_owned(),
+            },
+        };
+
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Decrypto game {id}</title>\
+             <style>body{{font-family:sans-serif}}td,th{{border:1px solid #999;padding:4px}}\
+             table{{border-collapse:collapse}}img{{height:60px;vertical-align:middle}}</style>\
+             </head><body>\n<h1>Decrypto game {id}</h1>\n<p>Finished {}. <strong>{}.</strong></p>\n",
+            self.finished_at_text(),
+            self.winner_text(),
+            id = self.game_id,
+        );
+
+        for team in Team::ORDER {
+            let players: Vec<_> = self
+                .players
+                .iter()
+                .filter(|p| p.team == Some(team))
+                .map(|p| escape_html(&p.nick))
+                .collect();
+            let _ = write!(
+                html,
+                "<h2>{} team</h2>\n<p>Players: {}</p>\n<table><tr><th>#</th><th>Keyword</th><th>Clues</th></tr>\n",
+                team_name(team),
+                players.join(", ")
+            );
+            for (i, (keyword, clues)) in self.keywords[team]
+                .iter()
+                .zip(self.clues_per_keyword(team))
+                .enumerate()
+            {
+                let clues: Vec<_> = clues.into_iter().map(clue).collect();
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    i + 1,
+                    escape_html(keyword),
+                    clues.join(", ")
+                );
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("<h2>Rounds</h2>\n");
+        for (i, round) in self.rounds.iter().enumerate() {
+            let _ = write!(html, "<h3>Round {}</h3>\n<ul>\n", i + 1);
+            for team in Team::ORDER {
+                let r = &round[team];
+                let clues: Vec<_> = r.non_computed.clues.iter().flatten().map(clue).collect();
+                let _ = writeln!(
+                    html,
+                    "<li><strong>{}</strong>: {} encrypted {} as {}. {}</li>",
+                    team_name(team),
+                    escape_html(self.nick(r.non_computed.encryptor)),
+                    r.non_computed.code,
+                    if clues.is_empty() {
+                        "nothing".to_owned()
+                    } else {
+                        clues.join(", ")
+                    },
+                    escape_html(&self.guess_text(round, team)),
+                );
+            }
+            html.push_str("</ul>\n");
+        }
+
+        if let Some(tiebreaker) = &self.tiebreaker {
+            html.push_str("<h2>Tiebreaker</h2>\n<ul>\n");
+            for team in Team::ORDER {
+                let _ = writeln!(
+                    html,
+                    "<li><strong>{}</strong> guessed {}</li>",
+                    team_name(team),
+                    escape_html(&self.tiebreaker_text(tiebreaker, team))
+                );
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("</body></html>\n");
+        html
+    }
+
+    /// Outcome of the decipher by the team, and the intercept of their code by the other team.
+    fn guess_text(&self, round: &PerTeam<CompletedRoundPerTeam>, team: Team) -> String {
+        let decipher = if round[team].score.decipher {
+            "Deciphered"
+        } else {
+            "Miscommunicated"
+        };
+        let intercept = match round[team.other()].score.intercept {
+            Some(true) => ", intercepted by the other team",
+            Some(false) => ", interception failed",
+            None => "",
+        };
+        format!("{decipher}{intercept}.")
+    }
+
+    fn tiebreaker_text(&self, tiebreaker: &TiebreakerRound, team: Team) -> String {
+        let guesses: Vec<_> = tiebreaker[team]
+            .guesses
+            .iter()
+            .zip(&self.keywords[team.other()])
+            .map(|(guess, keyword)| match guess {
+                Some(guess) if check_tiebreaker_guess(guess, keyword, &self.language) => {
+                    format!("{guess} (correct)")
+                }
+                Some(guess) => format!("{guess} (was {keyword})"),
+                None => format!("nothing (was {keyword})"),
+            })
+            .collect();
+        guesses.join(", ")
+    }
+}
+
+fn team_name(team: Team) -> &'static str {
+    if team == Team::WHITE {
+        "White"
+    } else {
+        "Black"
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes user text for a Markdown table cell or list item.
+/// Line breaks would end the row, so they are replaced with spaces.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' | '\r' => escaped.push(' '),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '!' | '|' | '~' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypto::{Code, RoundPerTeam, RoundResult, TimedOut};
+
+    fn record() -> GameRecord {
+        let (alice, bob) = (UserId::new(), UserId::new());
+        let round = |encryptor, clues: [&str; 3]| CompletedRoundPerTeam {
+            non_computed: RoundPerTeam {
+                encryptor,
+                code: Code(vec![0, 2, 1]),
+                clues: Some(clues.map(|c| Clue::Text(c.to_owned())).to_vec()),
+                decipher: Some(Code(vec![0, 2, 1])),
+                intercept: None,
+                timed_out: TimedOut::default(),
+                started_at: None,
+                encrypt_millis: Some(1000),
+            },
+            score: RoundResult {
+                intercept: None,
+                decipher: true,
+            },
+        };
+        GameRecord {
+            id: RecordId::new(),
+            game_id: GameId::new(),
+            finished_at: 0,
+            settings: GameSettings::default(),
+            language: Language::default(),
+            players: vec![
+                RecordPlayer {
+                    id: alice,
+                    nick: "<alice>".to_owned(),
+                    team: Some(Team::WHITE),
+                    is_bot: false,
+                },
+                RecordPlayer {
+                    id: bob,
+                    nick: "bob".to_owned(),
+                    team: Some(Team::BLACK),
+                    is_bot: false,
+                },
+            ],
+            winner: Some(Team::WHITE),
+            keywords: PerTeam([
+                ["apple", "dog", "sun", "car"].map(str::to_owned).to_vec(),
+                ["moon", "tree", "fish", "book"].map(str::to_owned).to_vec(),
+            ]),
+            rounds: vec![PerTeam([
+                round(alice, ["red", "bright", "bark"]),
+                round(bob, ["night", "river", "leaf"]),
+            ])],
+            tiebreaker: None,
+            drawings: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn clues_are_grouped_by_keyword() {
+        let record = record();
+        let clues = record.clues_per_keyword(Team::WHITE);
+        assert_eq!(clues[0].len(), 1);
+        assert!(matches!(clues[2][0], Clue::Text(text) if text == "bright"));
+        assert!(clues[3].is_empty());
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let html = record().render(ExportFormat::Html);
+        assert!(html.contains("&lt;alice&gt;"));
+        assert!(!html.contains("<alice>"));
+        assert!(
+            record()
+                .render(ExportFormat::Markdown)
+                .contains("| 2 | dog | bark |")
+        );
+    }
+
+    #[test]
+    fn markdown_is_escaped() {
+        let mut record = record();
+        record.rounds[0][Team::WHITE].non_computed.clues =
+            Some(vec![Clue::Text("a | x\n| c".to_owned())]);
+        let md = record.render(ExportFormat::Markdown);
+        assert!(md.contains("Players: \\<alice\\>"));
+        assert!(md.contains("| 1 | apple | a \\| x \\| c |"));
+        assert!(!md.contains("x\n"));
+    }
+
+    #[test]
+    fn json_roundtrips() {
+        let record = record();
+        let json = record.render(ExportFormat::Json);
+        let parsed: GameRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.id, record.id);
+        assert_eq!(parsed.rounds.len(), 1);
+    }
+}
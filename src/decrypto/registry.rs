@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use crate::decrypto::{
+    settings::GameSettings,
+    wordlist::{CUSTOM_WORDLIST, Wordlist, WordlistInfo, check_wordlist_size},
+};
+
+/// Smallest keyword count allowed by the settings.
+/// Lists that can't fill a game with it are rejected.
+const MIN_KEYWORD_COUNT: usize = 4;
+
+/// Identifies the version of a file on disk, to detect changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Wordlists loaded from `.txt` files in a directory.
+/// Every list is validated when loaded, and broken ones are logged and left out.
+#[derive(Debug, Clone)]
+pub struct WordlistRegistry {
+    dir: PathBuf,
+    wordlists: HashMap<String, Arc<Wordlist>>,
+    /// Stamps of all files seen in the directory, including broken ones.
+    stamps: HashMap<String, FileStamp>,
+}
+
+impl WordlistRegistry {
+    pub fn load(dir: impl Into<PathBuf>) -> Self {
+        let mut registry = Self {
+            dir: dir.into(),
+            wordlists: HashMap::new(),
+            stamps: HashMap::new(),
+        };
+        registry.reload();
+        tracing::info!(
+            "Loaded {} wordlists from {}",
+            registry.wordlists.len(),
+            registry.dir.display()
+        );
+        registry
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Arc<Wordlist>> {
+        self.wordlists.get(id)
+    }
+
+    /// Metadata for all wordlists, with the default one first and the rest sorted by name.
+    pub fn infos(&self) -> Vec<WordlistInfo> {
+        let mut infos: Vec<_> = self.wordlists.values().map(|w| w.info.clone()).collect();
+
+        let default = GameSettings::default().wordlists[0].id.clone();
+        infos.sort_by(|a, b| {
+            (a.id != default)
+                .cmp(&(b.id != default))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        infos
+    }
+
+    /// Rescans the directory, loading new and changed files and dropping removed ones.
+    /// Returns `true` if anything changed.
+    pub fn reload(&mut self) -> bool {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::error!(
+                    "Cannot read wordlist directory {}: {err}",
+                    self.dir.display()
+                );
+                return false;
+            }
+        };
+
+        let mut seen = HashMap::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".txt"))
+            else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() {
+                continue;
+            }
+            seen.insert(
+                id.to_owned(),
+                FileStamp {
+                    modified: metadata.modified().ok(),
+                    len: metadata.len(),
+                },
+            );
+        }
+
+        let mut changed = false;
+
+        self.stamps.retain(|id, _| {
+            if seen.contains_key(id) {
+                return true;
+            }
+            tracing::info!("Wordlist {id} removed");
+            changed |= self.wordlists.remove(id).is_some();
+            false
+        });
+
+        for (id, stamp) in seen {
+            if self.stamps.get(&id) == Some(&stamp) {
+                continue;
+            }
+            self.stamps.insert(id.clone(), stamp);
+            changed = true;
+
+            match load_file(&self.dir.join(format!("{id}.txt")), &id) {
+                Ok(wordlist) => {
+                    tracing::info!("Wordlist {id} loaded, {} keywords", wordlist.words.len());
+                    self.wordlists.insert(id, Arc::new(wordlist));
+                }
+                Err(err) => {
+                    tracing::error!("Wordlist {id} is broken and will not be used: {err}");
+                    self.wordlists.remove(&id);
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+fn load_file(path: &Path, id: &str) -> Result<Wordlist, String> {
+    if id == CUSTOM_WORDLIST {
+        return Err(format!("'{CUSTOM_WORDLIST}' is a reserved name"));
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let text = String::from_utf8(bytes).map_err(|e| format!("Not valid UTF-8: {e}"))?;
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let wordlist = Wordlist::parse(id, text)?;
+    wordlist.validate()?;
+    check_wordlist_size(wordlist.words.len(), MIN_KEYWORD_COUNT)?;
+    Ok(wordlist)
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("decrypto-wordlists-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn broken_wordlists_are_excluded() {
+        let dir = temp_dir();
+        fs::write(dir.join("ok.txt"), "a\nb\nc\nd\ne\nf\ng\nh").unwrap();
+        fs::write(dir.join("short.txt"), "a\nb").unwrap();
+        fs::write(dir.join("duplicates.txt"), "a\nb\nc\nd\ne\nf\ng\nA").unwrap();
+        fs::write(dir.join("binary.txt"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let registry = WordlistRegistry::load(&dir);
+        assert!(registry.get("ok").is_some());
+        assert_eq!(registry.infos().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn changes_are_reloaded() {
+        let dir = temp_dir();
+        let mut registry = WordlistRegistry::load(&dir);
+        assert!(!registry.reload());
+
+        fs::write(dir.join("new.txt"), "a\nb\nc\nd\ne\nf\ng\nh").unwrap();
+        assert!(registry.reload());
+        assert_eq!(registry.get("new").unwrap().words.len(), 8);
+
+        fs::write(dir.join("new.txt"), "a\nb\nc\nd\ne\nf\ng\nh\ni").unwrap();
+        assert!(registry.reload());
+        assert_eq!(registry.get("new").unwrap().words.len(), 9);
+
+        fs::remove_file(dir.join("new.txt")).unwrap();
+        assert!(registry.reload());
+        assert!(registry.get("new").is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
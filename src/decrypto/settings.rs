@@ -1,27 +1,42 @@
-use std::{array, collections::HashMap, fs, time::Duration};
+use std::{
+    array,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
-use rand::seq::SliceRandom;
-use serde::{Deserialize, Serialize};
+use rand::{
+    distr::{Distribution, weighted::WeightedIndex},
+    seq::SliceRandom,
+};
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
 
-use crate::decrypto::{Code, PerTeam, Team};
+use crate::decrypto::{
+    Code, PerTeam, Team,
+    registry::WordlistRegistry,
+    wordlist::{CUSTOM_WORDLIST, Wordlist, check_wordlist_size},
+};
+
+/// Most keywords a team can have. Keeps the pool the wordlists must fill reasonable.
+pub const MAX_KEYWORD_COUNT: usize = 16;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[serde(default, rename_all = "snake_case")]
 pub struct GameSettings {
     /// How soon start tiebreaker/draw procedure.
     /// Default 8. Min 3.
     pub round_limit: Option<usize>,
     // How many keywords to show in the game.
-    /// Default 4. Min 4.
+    /// Default 4. Min 4. Max [`MAX_KEYWORD_COUNT`].
     pub keyword_count: usize,
     /// How many clues per round. Also code length.
     /// Default 3. Min 3. Max `keyword_count`.
     pub clue_count: usize,
     /// How clues are given.
     pub clue_mode: ClueMode,
-    /// Keyword list to use for the game.
+    /// Keyword lists to pick keywords from.
     /// `/wordlists` returns a list of available wordlists.
-    pub wordlist: String,
+    pub wordlists: Vec<WordlistChoice>,
     /// Miscommunication limit before losing.
     /// Default 2. Min 1. Max `round_limit - 1`.
     pub miscommunication_limit: usize,
@@ -36,6 +51,8 @@ pub struct GameSettings {
     pub guess_time_limit: GuessTimeLimit,
     /// Time to do tiebreaker.
     pub tiebreaker_time_limit: GuessTimeLimit,
+    /// Allow players to ask for the most likely codes when guessing.
+    pub suggestions: bool,
 }
 impl Default for GameSettings {
     fn default() -> Self {
@@ -44,18 +61,40 @@ impl Default for GameSettings {
             keyword_count: 4,
             clue_count: 3,
             clue_mode: Default::default(),
-            wordlist: "original".to_string(),
+            wordlists: vec![WordlistChoice::new("original")],
             miscommunication_limit: 2,
             intercept_limit: 2,
             tiebreaker: Tiebreaker::default(),
             encrypt_time_limit: Default::default(),
             guess_time_limit: Default::default(),
             tiebreaker_time_limit: Default::default(),
+            suggestions: false,
         }
     }
 }
 impl GameSettings {
-    pub fn validate(&self) -> Result<(), String> {
+    /// Deserializes settings that must list every field.
+    /// Stored settings may leave out fields added later, but a client changing the settings
+    /// must not reset the ones it forgot to send.
+    pub fn deserialize_complete<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let expected = serde_json::to_value(Self::default()).expect("Settings should serialize");
+        if let (Some(given), Some(expected)) = (value.as_object(), expected.as_object())
+            && let Some(missing) = expected.keys().find(|key| !given.contains_key(*key))
+        {
+            return Err(D::Error::custom(format!("missing field `{missing}`")));
+        }
+        serde_json::from_value(value).map_err(D::Error::custom)
+    }
+
+    /// `custom_wordlist` is the list uploaded to the lobby, if any.
+    pub fn validate(
+        &self,
+        registry: &WordlistRegistry,
+        custom_wordlist: Option<&Arc<Wordlist>>,
+    ) -> Result<(), String> {
         if let Some(round_limit) = self.round_limit
             && round_limit < 3
         {
@@ -79,8 +118,10 @@ impl GameSettings {
                 self.miscommunication_limit
             ));
         }
-        if self.keyword_count < 4 {
-            return Err("Keyword count must be at least 4".to_string());
+        if self.keyword_count < 4 || self.keyword_count > MAX_KEYWORD_COUNT {
+            return Err(format!(
+                "Keyword count must be between 4 and {MAX_KEYWORD_COUNT}"
+            ));
         }
         if self.clue_count < 2 || self.clue_count > self.keyword_count {
             return Err(format!(
@@ -89,11 +130,123 @@ impl GameSettings {
             ));
         }
 
-        if !available_wordlists().contains(&self.wordlist) {
-            return Err(format!("Wordlist '{}' does not exist", self.wordlist));
+        self.validate_wordlists(&self.load_wordlists(registry, custom_wordlist)?, &[])
+    }
+
+    /// Checks that the selected wordlists can be mixed, and that the combined pool is big enough
+    /// once the `exclude`d keywords are left out.
+    fn validate_wordlists(
+        &self,
+        wordlists: &[Arc<Wordlist>],
+        exclude: &[String],
+    ) -> Result<(), String> {
+        let Some(first) = wordlists.first() else {
+            return Err("At least one wordlist must be selected".to_owned());
+        };
+        if let Some(other) = wordlists
+            .iter()
+            .find(|w| w.info.language != first.info.language)
+        {
+            return Err(format!(
+                "Wordlists must have the same language, '{}' is {} but '{}' is {}",
+                first.info.id, first.info.language, other.info.id, other.info.language
+            ));
+        }
+
+        let pools = self.keyword_pools(wordlists, exclude);
+        let mut quota_total: usize = 0;
+        for (choice, pool) in self.wordlists.iter().zip(&pools) {
+            if let Some(quota) = choice.quota {
+                // Checked first, as the quota comes from the client and could overflow below.
+                if quota > self.keyword_count {
+                    return Err(format!(
+                        "Quota of wordlist '{}' is {quota}, but only {} keywords are used",
+                        choice.id, self.keyword_count
+                    ));
+                }
+                if pool.len() < quota * 2 {
+                    return Err(format!(
+                        "Wordlist '{}' has {} unused keywords, quota of {quota} needs {}",
+                        choice.id,
+                        pool.len(),
+                        quota * 2
+                    ));
+                }
+                quota_total = quota_total.saturating_add(quota);
+            }
         }
 
-        Ok(())
+        if quota_total > self.keyword_count {
+            return Err(format!(
+                "Wordlist quotas add up to {quota_total}, but only {} keywords are used",
+                self.keyword_count
+            ));
+        }
+        if quota_total < self.keyword_count
+            && !self
+                .wordlists
+                .iter()
+                .any(|c| c.quota.is_none() && c.weight > 0)
+        {
+            return Err("Wordlist quotas must add up to the keyword count, \
+                or a wordlist without a quota must be selected"
+                .to_owned());
+        }
+
+        check_wordlist_size(pools.iter().map(|p| p.len()).sum(), self.keyword_count)
+    }
+
+    /// Looks up the selected wordlists, in the order they are selected.
+    /// `custom_wordlist` is used for the `"custom"` entry.
+    pub fn load_wordlists(
+        &self,
+        registry: &WordlistRegistry,
+        custom_wordlist: Option<&Arc<Wordlist>>,
+    ) -> Result<Vec<Arc<Wordlist>>, String> {
+        let mut seen = Vec::new();
+        let mut result = Vec::new();
+        for choice in &self.wordlists {
+            if seen.contains(&&choice.id) {
+                return Err(format!(
+                    "Wordlist '{}' is selected more than once",
+                    choice.id
+                ));
+            }
+            seen.push(&choice.id);
+
+            let wordlist = if choice.id == CUSTOM_WORDLIST {
+                custom_wordlist.ok_or("Custom wordlist selected, but none has been uploaded")?
+            } else {
+                registry
+                    .get(&choice.id)
+                    .ok_or_else(|| format!("Wordlist '{}' does not exist", choice.id))?
+            };
+            result.push(wordlist.clone());
+        }
+        Ok(result)
+    }
+
+    /// Keywords available from each selected wordlist, without the `exclude`d ones.
+    /// A keyword that appears in multiple lists is only kept in the first one.
+    fn keyword_pools(&self, wordlists: &[Arc<Wordlist>], exclude: &[String]) -> Vec<Vec<String>> {
+        let mut seen: HashSet<_> = match wordlists.first() {
+            Some(first) => exclude
+                .iter()
+                .map(|word| first.info.language.normalize(word))
+                .collect(),
+            None => HashSet::new(),
+        };
+        wordlists
+            .iter()
+            .map(|wordlist| {
+                wordlist
+                    .words
+                    .iter()
+                    .filter(|word| seen.insert(wordlist.info.language.normalize(word)))
+                    .cloned()
+                    .collect()
+            })
+            .collect()
     }
 
     /// Note that the code used zero-based indexing.
@@ -104,52 +257,89 @@ impl GameSettings {
         Code(data)
     }
 
-    pub fn pick_random_keywords(&self) -> PerTeam<Vec<String>> {
-        let mut keywords = self.load_wordlist();
-        assert!(keywords.len() >= self.keyword_count);
+    /// Picks distinct keywords for both teams from wordlists returned by [`Self::load_wordlists`],
+    /// skipping the `exclude`d ones.
+    /// Quotas are filled first, and the rest of the keywords are picked from wordlists
+    /// without a quota, choosing the list for each keyword by weight.
+    pub fn pick_random_keywords(
+        &self,
+        wordlists: &[Arc<Wordlist>],
+        exclude: &[String],
+    ) -> Result<PerTeam<Vec<String>>, String> {
+        self.validate_wordlists(wordlists, exclude)?;
 
-        keywords.shuffle(&mut rand::rng());
-        keywords.truncate(self.keyword_count * 2);
-        let other = keywords.split_off(self.keyword_count);
-        PerTeam::from([keywords, other])
-    }
+        let mut rng = rand::rng();
+        let mut pools = self.keyword_pools(wordlists, exclude);
+        for pool in &mut pools {
+            pool.shuffle(&mut rng);
+        }
 
-    pub fn load_wordlist(&self) -> Vec<String> {
-        assert!(
-            available_wordlists().contains(&self.wordlist),
-            "Wordlist '{}' does not exist",
-            self.wordlist
-        );
-        let path = format!("./wordlists/{}.txt", self.wordlist);
-        let Ok(wordlist) = fs::read_to_string(path) else {
-            return vec![];
-        };
-        wordlist
-            .lines()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect()
+        // Quotas of both teams are taken first, as the fallback below may use up the quota lists.
+        let mut teams = [Vec::new(), Vec::new()];
+        for keywords in &mut teams {
+            for (choice, pool) in self.wordlists.iter().zip(&mut pools) {
+                if let Some(quota) = choice.quota {
+                    keywords.extend(pool.split_off(pool.len() - quota));
+                }
+            }
+        }
+
+        for keywords in &mut teams {
+            while keywords.len() < self.keyword_count {
+                let weights: Vec<_> = self
+                    .wordlists
+                    .iter()
+                    .zip(&pools)
+                    .map(|(choice, pool)| {
+                        if choice.quota.is_none() && !pool.is_empty() {
+                            choice.weight
+                        } else {
+                            0
+                        }
+                    })
+                    .collect();
+
+                let index = match WeightedIndex::new(&weights) {
+                    Ok(dist) => dist.sample(&mut rng),
+                    // Weighted lists ran out, so fall back to leftovers of the quota lists.
+                    Err(_) => pools
+                        .iter()
+                        .position(|pool| !pool.is_empty())
+                        .expect("Pool size should be validated"),
+                };
+                keywords.push(pools[index].pop().expect("Pool should not be empty"));
+            }
+            keywords.shuffle(&mut rng);
+        }
+
+        Ok(PerTeam::from(teams))
     }
 }
 
-pub fn available_wordlists() -> Vec<String> {
-    match fs::read_dir("./wordlists") {
-        Ok(entries) => entries
-            .flatten()
-            .filter_map(|entry| {
-                let path = entry.path();
-                if path.is_file() {
-                    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                        file_name.strip_suffix(".txt").map(|s| s.to_owned())
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect(),
-        Err(_) => vec![],
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WordlistChoice {
+    /// Wordlist id from `/wordlists`, or `"custom"` for the list uploaded to the lobby.
+    pub id: String,
+    /// Relative chance of picking each keyword from this list.
+    /// Ignored if `quota` is set. Default 1.
+    #[serde(default = "WordlistChoice::default_weight")]
+    pub weight: u32,
+    /// Exact number of keywords per team to pick from this list.
+    #[serde(default)]
+    pub quota: Option<usize>,
+}
+impl WordlistChoice {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            weight: Self::default_weight(),
+            quota: None,
+        }
+    }
+
+    fn default_weight() -> u32 {
+        1
     }
 }
 
@@ -219,6 +409,179 @@ mod tests {
     #[test]
     fn default_settings_are_valid() {
         let settings = GameSettings::default();
-        assert!(settings.validate().is_ok());
+        assert!(
+            settings
+                .validate(&WordlistRegistry::load("./wordlists"), None)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn keyword_count_is_bounded() {
+        let registry = WordlistRegistry::load("./wordlists");
+        for keyword_count in [MAX_KEYWORD_COUNT + 1, usize::MAX] {
+            let settings = GameSettings {
+                keyword_count,
+                ..GameSettings::default()
+            };
+            assert!(settings.validate(&registry, None).is_err());
+        }
+    }
+
+    #[test]
+    fn changed_settings_must_be_complete() {
+        let mut value = serde_json::to_value(GameSettings::default()).unwrap();
+        assert!(GameSettings::deserialize_complete(value.clone()).is_ok());
+
+        value.as_object_mut().unwrap().remove("clue_count");
+        assert!(GameSettings::deserialize_complete(value.clone()).is_err());
+        // Stored settings are still read with defaults.
+        assert!(serde_json::from_value::<GameSettings>(value).is_ok());
+    }
+
+    fn wordlist(id: &str, words: &[&str]) -> Arc<Wordlist> {
+        Arc::new(Wordlist::parse(id, &words.join("\n")).unwrap())
+    }
+
+    #[test]
+    fn keywords_are_distinct_across_wordlists() {
+        let settings = GameSettings {
+            wordlists: vec![WordlistChoice::new("a"), WordlistChoice::new("b")],
+            ..GameSettings::default()
+        };
+        let wordlists = [
+            wordlist("a", &["one", "two", "three", "four", "five"]),
+            wordlist("b", &["One", "two", "six", "seven", "eight"]),
+        ];
+        assert!(settings.validate_wordlists(&wordlists, &[]).is_ok());
+
+        for _ in 0..100 {
+            let keywords = settings.pick_random_keywords(&wordlists, &[]).unwrap();
+            let mut all: Vec<_> = keywords
+                .0
+                .concat()
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect();
+            all.sort();
+            assert_eq!(
+                all,
+                [
+                    "eight", "five", "four", "one", "seven", "six", "three", "two"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn quotas_are_filled() {
+        let settings = GameSettings {
+            wordlists: vec![
+                WordlistChoice::new("a"),
+                WordlistChoice {
+                    quota: Some(1),
+                    ..WordlistChoice::new("b")
+                },
+            ],
+            ..GameSettings::default()
+        };
+        let wordlists = [
+            wordlist("a", &["a1", "a2", "a3", "a4", "a5", "a6", "a7", "a8"]),
+            wordlist("b", &["b1", "b2", "b3"]),
+        ];
+
+        for _ in 0..100 {
+            let keywords = settings.pick_random_keywords(&wordlists, &[]).unwrap();
+            assert!(keywords.both(|k| k.iter().filter(|w| w.starts_with('b')).count() == 1));
+        }
+    }
+
+    #[test]
+    fn quotas_survive_fallback_to_quota_lists() {
+        let settings = GameSettings {
+            keyword_count: 4,
+            wordlists: vec![
+                WordlistChoice {
+                    quota: Some(1),
+                    ..WordlistChoice::new("a")
+                },
+                WordlistChoice::new("c"),
+                WordlistChoice {
+                    quota: Some(1),
+                    ..WordlistChoice::new("d")
+                },
+            ],
+            ..GameSettings::default()
+        };
+        let wordlists = [
+            wordlist("a", &["a1", "a2"]),
+            wordlist("c", &["c1"]),
+            wordlist("d", &["d1", "d2", "d3", "d4", "d5"]),
+        ];
+        assert!(settings.validate_wordlists(&wordlists, &[]).is_ok());
+
+        for _ in 0..100 {
+            let keywords = settings.pick_random_keywords(&wordlists, &[]).unwrap();
+            assert!(keywords.both(|k| k.len() == 4));
+            assert!(keywords.both(|k| k.iter().any(|w| w.starts_with('a'))));
+        }
+    }
+
+    #[test]
+    fn combined_pool_must_be_big_enough() {
+        let settings = GameSettings {
+            wordlists: vec![WordlistChoice::new("a"), WordlistChoice::new("b")],
+            ..GameSettings::default()
+        };
+        let wordlists = [
+            wordlist("a", &["one", "two", "three", "four"]),
+            wordlist("b", &["one", "two", "three", "four", "five"]),
+        ];
+        assert!(settings.validate_wordlists(&wordlists, &[]).is_err());
+    }
+
+    #[test]
+    fn excluded_keywords_are_not_picked() {
+        let settings = GameSettings::default();
+        let wordlists = [wordlist(
+            "a",
+            &["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"],
+        )];
+        let exclude = ["1".to_owned(), "2".to_owned()];
+
+        let keywords = settings.pick_random_keywords(&wordlists, &exclude).unwrap();
+        assert!(keywords.both(|k| !k.contains(&exclude[0]) && !k.contains(&exclude[1])));
+
+        let exclude = ["1".to_owned(), "2".to_owned(), "3".to_owned()];
+        assert!(settings.pick_random_keywords(&wordlists, &exclude).is_err());
+    }
+
+    #[test]
+    fn quotas_must_fit_keyword_count() {
+        let settings = GameSettings {
+            wordlists: vec![WordlistChoice {
+                quota: Some(3),
+                ..WordlistChoice::new("a")
+            }],
+            ..GameSettings::default()
+        };
+        let wordlists = [wordlist("a", &["1", "2", "3", "4", "5", "6", "7", "8"])];
+        assert!(settings.validate_wordlists(&wordlists, &[]).is_err());
+
+        let huge = GameSettings {
+            wordlists: vec![
+                WordlistChoice {
+                    quota: Some(usize::MAX),
+                    ..WordlistChoice::new("a")
+                },
+                WordlistChoice {
+                    quota: Some(usize::MAX / 2 + 1),
+                    ..WordlistChoice::new("b")
+                },
+            ],
+            ..GameSettings::default()
+        };
+        let wordlists = [wordlists[0].clone(), wordlist("b", &["9"])];
+        assert!(huge.validate_wordlists(&wordlists, &[]).is_err());
     }
 }
@@ -0,0 +1,338 @@
+use std::{collections::HashSet, fmt};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+/// Reserved wordlist name that refers to the custom list uploaded to the lobby.
+pub const CUSTOM_WORDLIST: &str = "custom";
+
+/// Maximum number of entries in a custom wordlist.
+pub const MAX_CUSTOM_WORDLIST_LEN: usize = 10_000;
+
+/// Maximum length of a single keyword, in characters.
+pub const MAX_KEYWORD_LEN: usize = 64;
+
+/// Language of a wordlist, as an ISO 639-1 code.
+/// Determines how clues and guesses are compared against keywords.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Language(pub String);
+
+impl Default for Language {
+    fn default() -> Self {
+        Self("en".to_owned())
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Language {
+    /// Languages where letters with diacritics are separate letters of the alphabet,
+    /// and must not be folded into their base letters.
+    const KEEPS_DIACRITICS: &[&str] = &["fi", "sv", "et", "de", "da", "no", "is", "tr"];
+
+    /// Canonical form of the text for comparisons.
+    /// Case and whitespace are ignored, and diacritics are folded if the language allows it.
+    pub fn normalize(&self, text: &str) -> String {
+        let text: String = if Self::KEEPS_DIACRITICS.contains(&self.0.as_str()) {
+            text.nfc().collect()
+        } else {
+            text.nfd().filter(|c| !is_combining_mark(*c)).collect()
+        };
+
+        text.to_lowercase()
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Whether two texts are the same word in this language.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        self.normalize(a) == self.normalize(b)
+    }
+
+    /// Whether the text contains the keyword as whole words, so that `ice cream` is found in
+    /// `ice cream cone` but `apple` isn't found in `pineapple`.
+    pub fn contains_keyword(&self, text: &str, keyword: &str) -> bool {
+        let text = self.normalize(text);
+        let keyword = self.normalize(keyword);
+        let text: Vec<_> = text.split(' ').collect();
+        let keyword: Vec<_> = keyword.split(' ').collect();
+        text.windows(keyword.len()).any(|words| words == keyword)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+/// Metadata of a wordlist, from the header block of the file.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WordlistInfo {
+    /// Name used to select the wordlist in the settings.
+    pub id: String,
+    /// Human-readable name, defaults to the id.
+    pub name: String,
+    pub language: Language,
+    pub description: Option<String>,
+    pub word_count: usize,
+    pub difficulty: Option<Difficulty>,
+    pub tags: Vec<String>,
+}
+
+impl WordlistInfo {
+    /// Info of a list without a header.
+    fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            language: Language::default(),
+            description: None,
+            word_count: 0,
+            difficulty: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Wordlist {
+    pub info: WordlistInfo,
+    pub words: Vec<String>,
+}
+
+impl Wordlist {
+    /// Parses a wordlist with one keyword per line.
+    /// The file may start with a header block of `# key: value` lines:
+    ///
+    /// ```text
+    /// # name: Original
+    /// # language: en
+    /// # description: Keywords from the original game
+    /// # difficulty: medium
+    /// # tags: official, mixed
+    /// ```
+    ///
+    /// Lines starting with `#` after the header are comments.
+    pub fn parse(id: &str, text: &str) -> Result<Self, String> {
+        let mut info = WordlistInfo::new(id);
+
+        let mut lines = text.lines().map(str::trim).peekable();
+        while let Some(line) = lines.next_if(|line| line.starts_with('#')) {
+            let Some((key, value)) = line[1..].split_once(':') else {
+                return Err(format!(
+                    "Invalid header line '{line}', expected '# key: value'"
+                ));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "name" => info.name = value.to_owned(),
+                "language" => info.language = Language(value.to_lowercase()),
+                "description" => info.description = Some(value.to_owned()),
+                "difficulty" => {
+                    info.difficulty = Some(match value.to_lowercase().as_str() {
+                        "easy" => Difficulty::Easy,
+                        "medium" => Difficulty::Medium,
+                        "hard" => Difficulty::Hard,
+                        _ => return Err(format!("Unknown difficulty '{value}'")),
+                    })
+                }
+                "tags" => {
+                    info.tags = value
+                        .split(',')
+                        .map(|tag| tag.trim().to_owned())
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                }
+                other => return Err(format!("Unknown header key '{other}'")),
+            }
+        }
+
+        let words: Vec<String> = lines
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_owned())
+            .collect();
+        info.word_count = words.len();
+
+        Ok(Self { info, words })
+    }
+
+    /// Checks that keywords are not too long, don't contain control characters,
+    /// and that no keyword is listed twice.
+    /// Duplicates are compared with the normalization of the list's language,
+    /// as they would make it possible for both teams to get the same keyword.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        for word in &self.words {
+            if word.chars().count() > MAX_KEYWORD_LEN {
+                return Err(format!(
+                    "Keyword '{word}' is longer than {MAX_KEYWORD_LEN} characters"
+                ));
+            }
+
+            if word.chars().any(|c| c.is_control()) {
+                return Err(format!("Keyword '{word}' contains control characters"));
+            }
+
+            if !seen.insert(self.info.language.normalize(word)) {
+                return Err(format!("Keyword '{word}' is listed more than once"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validates a user-supplied keyword list, which may include a header block.
+/// Surrounding whitespace is trimmed and empty lines are skipped.
+pub fn parse_custom_wordlist(text: &str, keyword_count: usize) -> Result<Wordlist, String> {
+    let wordlist = Wordlist::parse(CUSTOM_WORDLIST, text)?;
+    check_custom_wordlist(&wordlist, keyword_count)?;
+    Ok(wordlist)
+}
+
+/// Validates user-supplied keywords, taken as they are: there is no header, and an entry
+/// starting with `#` is a keyword. Surrounding whitespace is trimmed and empty entries are skipped.
+pub fn custom_wordlist_from_words(
+    words: Vec<String>,
+    keyword_count: usize,
+) -> Result<Wordlist, String> {
+    let words: Vec<_> = words
+        .into_iter()
+        .map(|word| word.trim().to_owned())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let mut info = WordlistInfo::new(CUSTOM_WORDLIST);
+    info.word_count = words.len();
+    let wordlist = Wordlist { info, words };
+    check_custom_wordlist(&wordlist, keyword_count)?;
+    Ok(wordlist)
+}
+
+/// Line breaks inside an entry are control characters, so they are rejected by
+/// [`Wordlist::validate`] rather than splitting the entry into several keywords.
+fn check_custom_wordlist(wordlist: &Wordlist, keyword_count: usize) -> Result<(), String> {
+    if wordlist.words.len() > MAX_CUSTOM_WORDLIST_LEN {
+        return Err(format!(
+            "Wordlist can contain at most {MAX_CUSTOM_WORDLIST_LEN} keywords"
+        ));
+    }
+
+    wordlist.validate()?;
+    check_wordlist_size(wordlist.words.len(), keyword_count)
+}
+
+/// Both teams need their own set of keywords, so the list must have at least twice the keyword count.
+pub fn check_wordlist_size(len: usize, keyword_count: usize) -> Result<(), String> {
+    let needed = keyword_count.saturating_mul(2);
+    if len < needed {
+        return Err(format!(
+            "Wordlist must contain at least {needed} keywords, got {len}"
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_wordlist_is_trimmed() {
+        let wordlist = parse_custom_wordlist(" a\nb \n\nc\nd\n  \ne\nf\ng\nh", 4).unwrap();
+        assert_eq!(wordlist.words, ["a", "b", "c", "d", "e", "f", "g", "h"]);
+    }
+
+    #[test]
+    fn custom_wordlist_rejects_duplicates() {
+        let err = parse_custom_wordlist("a\nb\nc\nd\ne\nf\ng\nA", 4).unwrap_err();
+        assert!(err.contains("more than once"));
+    }
+
+    #[test]
+    fn custom_words_have_no_header() {
+        let words = |words: &[&str]| words.iter().map(|w| (*w).to_owned()).collect::<Vec<_>>();
+        let wordlist = custom_wordlist_from_words(
+            words(&[
+                "# language: fi",
+                "#1",
+                " b ",
+                "",
+                "c",
+                "d",
+                "e",
+                "f",
+                "g",
+                "h",
+            ]),
+            4,
+        )
+        .unwrap();
+        assert_eq!(wordlist.info.language, Language::default());
+        assert_eq!(wordlist.words[..3], ["# language: fi", "#1", "b"]);
+
+        let split = words(&["a\nb", "c", "d", "e", "f", "g", "h", "i"]);
+        assert!(custom_wordlist_from_words(split, 4).is_err());
+    }
+
+    #[test]
+    fn custom_wordlist_requires_enough_keywords() {
+        assert!(parse_custom_wordlist("a\nb\nc\nd\ne\nf\ng", 4).is_err());
+        assert!(parse_custom_wordlist("a\nb\nc\nd\ne\nf\ng\nh", 4).is_ok());
+        assert!(parse_custom_wordlist("a\nb\nc\nd\ne\nf\ng\nh", usize::MAX).is_err());
+    }
+
+    #[test]
+    fn custom_wordlist_rejects_long_keywords() {
+        let long = "x".repeat(MAX_KEYWORD_LEN + 1);
+        assert!(parse_custom_wordlist(&long, 1).is_err());
+    }
+
+    #[test]
+    fn header_is_parsed() {
+        let wordlist = Wordlist::parse(
+            "test",
+            "# name: Test list\n# language: FI\n# difficulty: Hard\n# tags: a, b,\nkissa\n# comment\nkoira\n",
+        )
+        .unwrap();
+        assert_eq!(wordlist.info.name, "Test list");
+        assert_eq!(wordlist.info.language, Language("fi".to_owned()));
+        assert_eq!(wordlist.info.difficulty, Some(Difficulty::Hard));
+        assert_eq!(wordlist.info.tags, ["a", "b"]);
+        assert_eq!(wordlist.info.word_count, 2);
+        assert_eq!(wordlist.words, ["kissa", "koira"]);
+    }
+
+    #[test]
+    fn unknown_header_key_is_rejected() {
+        assert!(Wordlist::parse("test", "# colour: blue\nword").is_err());
+    }
+
+    #[test]
+    fn normalization_depends_on_language() {
+        let en = Language("en".to_owned());
+        let fi = Language("fi".to_owned());
+        assert!(en.matches(" Café ", "cafe"));
+        assert!(en.matches("ice-cream", "ice  cream"));
+        assert!(!fi.matches("sää", "saa"));
+        assert!(fi.matches("SÄÄ", "sää"));
+    }
+
+    #[test]
+    fn keyword_is_found_in_clue() {
+        let en = Language::default();
+        assert!(en.contains_keyword("Big Apple", "apple"));
+        assert!(!en.contains_keyword("pineapple", "apple"));
+        assert!(en.contains_keyword("Ice-cream cone", "ice cream"));
+        assert!(!en.contains_keyword("cream of ice", "ice cream"));
+    }
+}
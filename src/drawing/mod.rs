@@ -0,0 +1,460 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64ct::{Base64, Encoding};
+use image::{ImageFormat, ImageReader};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Limits,
+    drawing::strokes::Strokes,
+    id::{DrawingId, UserId},
+    message::Clue,
+};
+
+pub mod strokes;
+
+/// Extension of drawings spilled to disk.
+const SPILL_EXTENSION: &str = "drawing";
+
+/// Drawing uploaded to a game.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Drawing {
+    pub uploader: UserId,
+    pub format: DrawingFormat,
+    #[serde(skip)]
+    pub data: DrawingData,
+    /// When the drawing was uploaded or last replaced.
+    #[serde(skip)]
+    pub uploaded_at: Instant,
+    /// Set once the drawing is submitted as a clue, after which it cannot be replaced.
+    pub locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawingFormat {
+    /// Png image, as uploaded.
+    Png,
+    /// [`Strokes`], in their compact binary form.
+    Strokes,
+}
+
+/// Format to serve a drawing in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderFormat {
+    #[default]
+    Png,
+    Svg,
+    /// The strokes as uploaded, for replaying them on the client.
+    Json,
+}
+
+/// Query of `GET /drawing/{game_id}/{drawing_id}`.
+/// Only stroke drawings can be resized or rendered as svg.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct RenderOptions {
+    pub format: RenderFormat,
+    /// Box to fit the drawing in, keeping its aspect ratio.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Only show what was drawn in the first `until` milliseconds, for timelapses.
+    pub until: Option<u32>,
+}
+
+/// Where the bytes of a drawing are kept.
+#[derive(Debug, Clone)]
+pub enum DrawingData {
+    Memory(Arc<[u8]>),
+    Disk(Arc<SpilledFile>),
+}
+
+/// File holding a drawing on disk, removed once the last reference to it is dropped.
+#[derive(Debug)]
+pub struct SpilledFile {
+    path: PathBuf,
+    len: usize,
+}
+
+impl Drop for SpilledFile {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        let remove = move || {
+            if let Err(err) = fs::remove_file(&path) {
+                tracing::warn!("Cannot remove drawing {}: {err}", path.display());
+            }
+        };
+        // Drawings are usually dropped with the state locked, so the file is removed on the
+        // blocking pool. Files left behind at shutdown are cleared by `clear_spill_dir`.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(remove)),
+            Err(_) => remove(),
+        }
+    }
+}
+
+impl DrawingData {
+    /// Keeps the bytes in memory, or writes them to `spill_dir` if one is given.
+    /// Blocks on file IO.
+    pub fn store(bytes: Vec<u8>, id: DrawingId, spill_dir: Option<&Path>) -> io::Result<Self> {
+        let Some(dir) = spill_dir else {
+            return Ok(Self::Memory(bytes.into()));
+        };
+        // Replacing a drawing writes a new file, so that the old one can still be read meanwhile.
+        let path = dir.join(format!(
+            "{}-{}.{SPILL_EXTENSION}",
+            id.0,
+            uuid::Uuid::new_v4()
+        ));
+        let len = bytes.len();
+        fs::write(&path, bytes)?;
+        Ok(Self::Disk(Arc::new(SpilledFile { path, len })))
+    }
+
+    /// Size of the drawing, in bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Memory(bytes) => bytes.len(),
+            Self::Disk(file) => file.len,
+        }
+    }
+
+    pub async fn load(&self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Memory(bytes) => Ok(bytes.to_vec()),
+            Self::Disk(file) => tokio::fs::read(&file.path).await,
+        }
+    }
+}
+
+/// Removes drawings left over from an earlier run from the spill directory.
+pub fn clear_spill_dir(dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == SPILL_EXTENSION) {
+            fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Limits how many drawings a game can hold, both in total and per uploader.
+/// Only checked for new drawings, replacing one doesn't count against the quota.
+pub fn check_drawing_quota(
+    drawings: &HashMap<DrawingId, Drawing>,
+    user_id: UserId,
+    limits: &Limits,
+) -> Result<(), String> {
+    if drawings.len() >= limits.max_drawings_per_game {
+        return Err("This game has too many drawings".to_owned());
+    }
+    let own = drawings
+        .values()
+        .filter(|drawing| drawing.uploader == user_id)
+        .count();
+    if own >= limits.max_drawings_per_user {
+        return Err(format!(
+            "You can upload at most {} drawings per game",
+            limits.max_drawings_per_user
+        ));
+    }
+    Ok(())
+}
+
+/// Removes drawings that were not used as a clue within `timeout` of being uploaded.
+/// Returns the number of drawings removed.
+pub fn collect_unused_drawings(
+    drawings: &mut HashMap<DrawingId, Drawing>,
+    timeout: Duration,
+) -> usize {
+    let before = drawings.len();
+    drawings.retain(|_, drawing| drawing.locked || drawing.uploaded_at.elapsed() < timeout);
+    before - drawings.len()
+}
+
+/// Drawings can be used as clues or replaced only by their uploader, and only until
+/// they have been submitted as a clue.
+pub fn check_drawing_owned(
+    drawings: &HashMap<DrawingId, Drawing>,
+    user_id: UserId,
+    drawing_id: DrawingId,
+) -> Result<(), String> {
+    let Some(drawing) = drawings.get(&drawing_id) else {
+        return Err("Drawing not found".to_owned());
+    };
+    if drawing.uploader != user_id {
+        return Err("Drawing was uploaded by someone else".to_owned());
+    }
+    if drawing.locked {
+        return Err("Drawing has already been used as a clue".to_owned());
+    }
+    Ok(())
+}
+
+/// Checks that a drawing is given as [`Clue::Strokes`] if it was uploaded as strokes,
+/// and as [`Clue::Drawing`] otherwise, so that clients can tell them apart from the clue.
+pub fn check_clue_format(
+    drawings: &HashMap<DrawingId, Drawing>,
+    clue: &Clue,
+) -> Result<(), String> {
+    let Some((drawing_id, format)) = clue.drawing() else {
+        return Ok(());
+    };
+    let Some(drawing) = drawings.get(&drawing_id) else {
+        return Err("Drawing not found".to_owned());
+    };
+    match (drawing.format, format) {
+        (DrawingFormat::Png, DrawingFormat::Strokes) => {
+            Err("Drawing is a png, give it as a drawing clue".to_owned())
+        }
+        (DrawingFormat::Strokes, DrawingFormat::Png) => {
+            Err("Drawing is made of strokes, give it as a strokes clue".to_owned())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates an uploaded drawing, which is either a png or [`Strokes`] as JSON.
+/// Returns the bytes to store.
+pub fn sanitize_drawing(
+    content_type: Option<&str>,
+    bytes: &[u8],
+    max_width: u32,
+    max_height: u32,
+) -> Result<(DrawingFormat, Vec<u8>), String> {
+    if content_type.is_some_and(|t| t.starts_with("application/json")) {
+        let strokes = Strokes::parse(bytes, max_width, max_height)?;
+        Ok((DrawingFormat::Strokes, strokes.encode()))
+    } else {
+        let png = sanitize_png(bytes, max_width, max_height)?;
+        Ok((DrawingFormat::Png, png))
+    }
+}
+
+/// Renders a stored drawing, returning its content type and bytes.
+/// Stroke drawings are never rendered larger than `max_width`x`max_height`.
+pub fn render_drawing(
+    format: DrawingFormat,
+    bytes: Vec<u8>,
+    options: &RenderOptions,
+    max_width: u32,
+    max_height: u32,
+) -> Result<(&'static str, Vec<u8>), String> {
+    let strokes = match format {
+        DrawingFormat::Png if options.format == RenderFormat::Png => {
+            return Ok(("image/png", bytes));
+        }
+        DrawingFormat::Png => return Err("Png drawings can only be served as png".to_owned()),
+        DrawingFormat::Strokes => Strokes::decode(&bytes)?,
+    };
+
+    let max_scale =
+        (max_width as f32 / strokes.width as f32).min(max_height as f32 / strokes.height as f32);
+    let scale = strokes.fit_scale(options.width, options.height, max_scale);
+    if scale <= 0.0 {
+        return Err("Width and height must be positive".to_owned());
+    }
+
+    match options.format {
+        RenderFormat::Png => Ok(("image/png", strokes.render_png(scale, options.until)?)),
+        RenderFormat::Svg => Ok((
+            "image/svg+xml",
+            strokes.render_svg(scale, options.until).into_bytes(),
+        )),
+        RenderFormat::Json => Ok((
+            "application/json",
+            serde_json::to_vec(&strokes).expect("Strokes should serialize"),
+        )),
+    }
+}
+
+/// Turns a stored drawing into a data URL, for embedding it in exported games.
+/// Stroke drawings are embedded as svg, which stays sharp and small.
+pub fn drawing_data_url(format: DrawingFormat, bytes: &[u8]) -> Result<String, String> {
+    let (content_type, bytes) = match format {
+        DrawingFormat::Png => ("image/png", bytes.to_vec()),
+        DrawingFormat::Strokes => (
+            "image/svg+xml",
+            Strokes::decode(bytes)?.render_svg(1.0, None).into_bytes(),
+        ),
+    };
+    Ok(format!(
+        "data:{content_type};base64,{}",
+        Base64::encode_string(&bytes)
+    ))
+}
+
+/// Decodes the uploaded png, checks its dimensions and re-encodes it.
+/// Re-encoding drops all metadata chunks, and anything else that isn't pixels.
+pub fn sanitize_png(bytes: &[u8], max_width: u32, max_height: u32) -> Result<Vec<u8>, String> {
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), ImageFormat::Png);
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(max_width);
+    limits.max_image_height = Some(max_height);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|e| match e {
+        image::ImageError::Limits(_) => {
+            format!("Drawing must be at most {max_width}x{max_height} pixels")
+        }
+        _ => "Drawing is not a valid png image".to_owned(),
+    })?;
+
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode drawing: {e}"))?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, RgbaImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn valid_png_is_accepted() {
+        assert!(sanitize_png(&png(80, 60), 800, 600).is_ok());
+    }
+
+    #[test]
+    fn oversized_png_is_rejected() {
+        let err = sanitize_png(&png(801, 600), 800, 600).unwrap_err();
+        assert!(err.contains("at most"));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert!(sanitize_png(b"<svg></svg>", 800, 600).is_err());
+    }
+
+    fn drawing(uploader: UserId) -> Drawing {
+        Drawing {
+            uploader,
+            format: DrawingFormat::Png,
+            data: DrawingData::Memory(png(1, 1).into()),
+            uploaded_at: Instant::now(),
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn only_unlocked_own_drawings_can_be_used() {
+        let owner = UserId::new();
+        let id = DrawingId::new();
+        let mut drawings = HashMap::new();
+        drawings.insert(id, drawing(owner));
+
+        assert!(check_drawing_owned(&drawings, owner, id).is_ok());
+        assert!(check_drawing_owned(&drawings, UserId::new(), id).is_err());
+        assert!(check_drawing_owned(&drawings, owner, DrawingId::new()).is_err());
+
+        drawings.get_mut(&id).unwrap().locked = true;
+        assert!(check_drawing_owned(&drawings, owner, id).is_err());
+    }
+
+    #[test]
+    fn clue_variant_matches_format() {
+        let id = DrawingId::new();
+        let mut drawings = HashMap::new();
+        drawings.insert(id, drawing(UserId::new()));
+
+        assert!(check_clue_format(&drawings, &Clue::Drawing(id)).is_ok());
+        assert!(check_clue_format(&drawings, &Clue::Strokes(id)).is_err());
+
+        drawings.get_mut(&id).unwrap().format = DrawingFormat::Strokes;
+        assert!(check_clue_format(&drawings, &Clue::Strokes(id)).is_ok());
+        assert!(check_clue_format(&drawings, &Clue::Drawing(id)).is_err());
+    }
+
+    #[test]
+    fn quota_is_per_game_and_per_user() {
+        let limits = Limits {
+            max_drawings_per_game: 3,
+            max_drawings_per_user: 2,
+            ..Limits::default()
+        };
+        let (a, b) = (UserId::new(), UserId::new());
+        let mut drawings = HashMap::new();
+        drawings.insert(DrawingId::new(), drawing(a));
+        drawings.insert(DrawingId::new(), drawing(a));
+
+        assert!(check_drawing_quota(&drawings, a, &limits).is_err());
+        assert!(check_drawing_quota(&drawings, b, &limits).is_ok());
+
+        drawings.insert(DrawingId::new(), drawing(b));
+        assert!(check_drawing_quota(&drawings, b, &limits).is_err());
+    }
+
+    #[test]
+    fn unused_drawings_are_collected() {
+        let user = UserId::new();
+        let (used, unused) = (DrawingId::new(), DrawingId::new());
+        let mut drawings = HashMap::new();
+        drawings.insert(used, drawing(user));
+        drawings.insert(unused, drawing(user));
+        drawings.get_mut(&used).unwrap().locked = true;
+
+        assert_eq!(collect_unused_drawings(&mut drawings, Duration::ZERO), 1);
+        assert!(drawings.contains_key(&used));
+    }
+
+    #[test]
+    fn spilled_drawings_are_removed_on_drop() {
+        let dir = std::env::temp_dir().join(format!("decrypto-drawings-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+
+        let data = DrawingData::store(png(1, 1), DrawingId::new(), Some(&dir)).unwrap();
+        let DrawingData::Disk(file) = &data else {
+            panic!("Should be spilled");
+        };
+        let path = file.path.clone();
+        assert!(path.exists());
+        drop(data);
+        assert!(!path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn spilled_drawings_are_removed_in_the_background() {
+        let dir = std::env::temp_dir().join(format!("decrypto-drawings-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+
+        let data = DrawingData::store(png(1, 1), DrawingId::new(), Some(&dir)).unwrap();
+        let DrawingData::Disk(file) = &data else {
+            panic!("Should be spilled");
+        };
+        let path = file.path.clone();
+        drop(data);
+        for _ in 0..100 {
+            if !path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(!path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use tiny_skia::{
+    FillRule, LineCap, LineJoin, Paint, PathBuilder, Pixmap, Stroke as SkiaStroke, Transform,
+};
+
+/// Maximum number of strokes in a drawing.
+pub const MAX_STROKES: usize = 1_000;
+
+/// Maximum number of points over all strokes of a drawing.
+pub const MAX_POINTS: usize = 50_000;
+
+/// Bounds of the stroke width, in canvas pixels.
+const MIN_STROKE_WIDTH: f32 = 0.5;
+const MAX_STROKE_WIDTH: f32 = 200.0;
+
+/// Drawing made of strokes on a white canvas, in the order they were drawn:
+///
+/// ```json
+/// {"width": 800, "height": 600, "strokes": [
+///     {"color": "#ff0000", "width": 10, "points": [[10, 20, 0], [15, 25, 16]]}
+/// ]}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct Strokes {
+    /// Size of the canvas, in pixels.
+    pub width: u32,
+    pub height: u32,
+    pub strokes: Vec<Stroke>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub struct Stroke {
+    pub color: Color,
+    pub width: f32,
+    pub points: Vec<Point>,
+}
+
+/// Position on the canvas, and milliseconds since the drawing was started.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point(pub f32, pub f32, pub u32);
+
+/// RGBA color, written as `#rrggbb` or `#rrggbbaa`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub [u8; 4]);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value
+            .strip_prefix('#')
+            .filter(|hex| matches!(hex.len(), 6 | 8) && hex.is_ascii())
+            .ok_or_else(|| format!("Invalid color '{value}'"))?;
+
+        let mut rgba = [255; 4];
+        for (i, channel) in rgba.iter_mut().take(hex.len() / 2).enumerate() {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("Invalid color '{value}'"))?;
+        }
+        Ok(Self(rgba))
+    }
+}
+
+impl From<Color> for String {
+    fn from(Color([r, g, b, a]): Color) -> Self {
+        if a == 255 {
+            format!("#{r:02x}{g:02x}{b:02x}")
+        } else {
+            format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+        }
+    }
+}
+
+impl Strokes {
+    pub fn parse(json: &[u8], max_width: u32, max_height: u32) -> Result<Self, String> {
+        let strokes: Self =
+            serde_json::from_slice(json).map_err(|e| format!("Invalid stroke drawing: {e}"))?;
+        strokes.validate(max_width, max_height)?;
+        Ok(strokes)
+    }
+
+    /// Checks the canvas size and that every point is on it,
+    /// and that timestamps never go backwards.
+    pub fn validate(&self, max_width: u32, max_height: u32) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 || self.width > max_width || self.height > max_height
+        {
+            return Err(format!(
+                "Drawing must be at most {max_width}x{max_height} pixels"
+            ));
+        }
+        if self.strokes.len() > MAX_STROKES {
+            return Err(format!("Drawing can have at most {MAX_STROKES} strokes"));
+        }
+        if self.strokes.iter().map(|s| s.points.len()).sum::<usize>() > MAX_POINTS {
+            return Err(format!("Drawing can have at most {MAX_POINTS} points"));
+        }
+
+        let mut time = 0;
+        for stroke in &self.strokes {
+            if !(MIN_STROKE_WIDTH..=MAX_STROKE_WIDTH).contains(&stroke.width) {
+                return Err(format!(
+                    "Stroke width must be between {MIN_STROKE_WIDTH} and {MAX_STROKE_WIDTH}"
+                ));
+            }
+            if stroke.points.is_empty() {
+                return Err("Strokes must have at least one point".to_owned());
+            }
+            for &Point(x, y, t) in &stroke.points {
+                if !(0.0..=self.width as f32).contains(&x)
+                    || !(0.0..=self.height as f32).contains(&y)
+                {
+                    return Err("Stroke goes outside the canvas".to_owned());
+                }
+                if t < time {
+                    return Err("Stroke timestamps must not go backwards".to_owned());
+                }
+                time = t;
+            }
+        }
+        Ok(())
+    }
+
+    /// Time from the first point to the last one, in milliseconds.
+    pub fn duration(&self) -> u32 {
+        self.strokes
+            .last()
+            .and_then(|stroke| stroke.points.last())
+            .map_or(0, |point| point.2)
+    }
+
+    /// Scale that fits the canvas within the given size, keeping the aspect ratio.
+    /// Missing dimensions don't constrain the scale, and it never exceeds `max_scale`.
+    pub fn fit_scale(&self, width: Option<u32>, height: Option<u32>, max_scale: f32) -> f32 {
+        let scale_x = width.map_or(max_scale, |w| w as f32 / self.width as f32);
+        let scale_y = height.map_or(max_scale, |h| h as f32 / self.height as f32);
+        scale_x.min(scale_y).min(max_scale)
+    }
+
+    fn scaled_size(&self, scale: f32) -> (u32, u32) {
+        (
+            ((self.width as f32 * scale).round() as u32).max(1),
+            ((self.height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    /// Points of each stroke drawn at most `until` milliseconds in, for timelapses.
+    fn visible_points(&self, until: Option<u32>) -> impl Iterator<Item = (&Stroke, &[Point])> {
+        self.strokes.iter().filter_map(move |stroke| {
+            let count = match until {
+                Some(until) => stroke.points.partition_point(|point| point.2 <= until),
+                None => stroke.points.len(),
+            };
+            (count > 0).then(|| (stroke, &stroke.points[..count]))
+        })
+    }
+
+    pub fn render_png(&self, scale: f32, until: Option<u32>) -> Result<Vec<u8>, String> {
+        let (width, height) = self.scaled_size(scale);
+        let mut pixmap = Pixmap::new(width, height).ok_or("Invalid drawing size")?;
+        pixmap.fill(tiny_skia::Color::WHITE);
+
+        let transform = Transform::from_scale(scale, scale);
+        for (stroke, points) in self.visible_points(until) {
+            let Color([r, g, b, a]) = stroke.color;
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(r, g, b, a);
+            paint.anti_alias = true;
+
+            let [Point(x, y, _), rest @ ..] = points else {
+                continue;
+            };
+            if rest.is_empty() {
+                // A single point is a dot as wide as the stroke.
+                if let Some(path) = PathBuilder::from_circle(*x, *y, stroke.width / 2.0) {
+                    pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+                }
+                continue;
+            }
+
+            let mut builder = PathBuilder::new();
+            builder.move_to(*x, *y);
+            for Point(x, y, _) in rest {
+                builder.line_to(*x, *y);
+            }
+            let Some(path) = builder.finish() else {
+                continue;
+            };
+            let skia_stroke = SkiaStroke {
+                width: stroke.width,
+                line_cap: LineCap::Round,
+                line_join: LineJoin::Round,
+                ..Default::default()
+            };
+            pixmap.stroke_path(&path, &paint, &skia_stroke, transform, None);
+        }
+
+        pixmap
+            .encode_png()
+            .map_err(|e| format!("Failed to encode drawing: {e}"))
+    }
+
+    pub fn render_svg(&self, scale: f32, until: Option<u32>) -> String {
+        let (width, height) = self.scaled_size(scale);
+        let mut svg = format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {} {}">"##,
+            self.width, self.height
+        );
+        svg.push_str(r##"<rect width="100%" height="100%" fill="#ffffff"/>"##);
+
+        for (stroke, points) in self.visible_points(until) {
+            let Color([r, g, b, a]) = stroke.color;
+            let mut d = String::new();
+            for (i, Point(x, y, _)) in points.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(d, "{command}{x} {y}");
+            }
+            if points.len() == 1 {
+                // Zero length segment, drawn as a dot by the round cap.
+                let Point(x, y, _) = points[0];
+                let _ = write!(d, "L{x} {y}");
+            }
+            let _ = write!(
+                svg,
+                r##"<path d="{d}" fill="none" stroke="#{r:02x}{g:02x}{b:02x}" stroke-opacity="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"##,
+                a as f32 / 255.0,
+                stroke.width
+            );
+        }
+
+        svg.push_str("</svg>");
+        svg
+    }
+
+    /// Compact binary form for storage, about a third of the size of the JSON.
+    /// All numbers are little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let points: usize = self.strokes.iter().map(|s| s.points.len()).sum();
+        let mut bytes = Vec::with_capacity(12 + self.strokes.len() * 12 + points * 12);
+        bytes.extend(self.width.to_le_bytes());
+        bytes.extend(self.height.to_le_bytes());
+        bytes.extend((self.strokes.len() as u32).to_le_bytes());
+        for stroke in &self.strokes {
+            bytes.extend(stroke.color.0);
+            bytes.extend(stroke.width.to_le_bytes());
+            bytes.extend((stroke.points.len() as u32).to_le_bytes());
+            for Point(x, y, t) in &stroke.points {
+                bytes.extend(x.to_le_bytes());
+                bytes.extend(y.to_le_bytes());
+                bytes.extend(t.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let stroke_count = reader.u32()?;
+        let mut strokes = Vec::new();
+        for _ in 0..stroke_count {
+            let color = Color(reader.u32()?.to_le_bytes());
+            let width = f32::from_bits(reader.u32()?);
+            let point_count = reader.u32()?;
+            let mut points = Vec::new();
+            for _ in 0..point_count {
+                points.push(Point(
+                    f32::from_bits(reader.u32()?),
+                    f32::from_bits(reader.u32()?),
+                    reader.u32()?,
+                ));
+            }
+            strokes.push(Stroke {
+                color,
+                width,
+                points,
+            });
+        }
+        if !reader.0.is_empty() {
+            return Err("Trailing bytes after stroke drawing".to_owned());
+        }
+        Ok(Self {
+            width,
+            height,
+            strokes,
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u32(&mut self) -> Result<u32, String> {
+        let Some((head, rest)) = self.0.split_first_chunk() else {
+            return Err("Truncated stroke drawing".to_owned());
+        };
+        self.0 = rest;
+        Ok(u32::from_le_bytes(*head))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWING: &str = r##"{"width": 80, "height": 60, "strokes": [
+        {"color": "#ff0000", "width": 4, "points": [[10, 10, 0], [20, 30, 16], [40, 30, 32]]},
+        {"color": "#00000080", "width": 2, "points": [[5, 5, 100]]}
+    ]}"##;
+
+    #[test]
+    fn encoding_roundtrips() {
+        let strokes = Strokes::parse(DRAWING.as_bytes(), 800, 600).unwrap();
+        assert_eq!(Strokes::decode(&strokes.encode()).unwrap(), strokes);
+        assert_eq!(strokes.strokes[1].color, Color([0, 0, 0, 0x80]));
+        assert_eq!(strokes.duration(), 100);
+    }
+
+    #[test]
+    fn invalid_drawings_are_rejected() {
+        let outside = r##"{"width": 80, "height": 60, "strokes": [
+            {"color": "#ff0000", "width": 4, "points": [[81, 10, 0]]}
+        ]}"##;
+        let backwards = r##"{"width": 80, "height": 60, "strokes": [
+            {"color": "#ff0000", "width": 4, "points": [[1, 1, 10], [2, 2, 5]]}
+        ]}"##;
+        let color = r##"{"width": 80, "height": 60, "strokes": [
+            {"color": "red", "width": 4, "points": [[1, 1, 0]]}
+        ]}"##;
+        for json in [outside, backwards, color] {
+            assert!(Strokes::parse(json.as_bytes(), 800, 600).is_err());
+        }
+        assert!(Strokes::parse(DRAWING.as_bytes(), 40, 30).is_err());
+    }
+
+    #[test]
+    fn renders_at_requested_size() {
+        let strokes = Strokes::parse(DRAWING.as_bytes(), 800, 600).unwrap();
+        let scale = strokes.fit_scale(Some(40), None, 4.0);
+        assert_eq!(scale, 0.5);
+
+        let png = strokes.render_png(scale, None).unwrap();
+        let image = image::load_from_memory(&png).unwrap();
+        assert_eq!((image.width(), image.height()), (40, 30));
+
+        let svg = strokes.render_svg(scale, Some(50));
+        assert!(svg.contains(r#"width="40" height="30""#));
+        assert_eq!(svg.matches("<path").count(), 1);
+    }
+}
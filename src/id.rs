@@ -1,18 +1,33 @@
-use core::fmt;
+use core::{fmt, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 /// Secret held by the user to prove their identity.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Redacted when debug printed, so it never ends up in the logs.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct UserSecret(Uuid);
 
+impl fmt::Debug for UserSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UserSecret(<redacted>)")
+    }
+}
+
 impl UserSecret {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
 }
 
+impl FromStr for UserSecret {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Uuid::parse_str(s).map(Self)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ConnectionId(Uuid);
 
@@ -22,6 +37,12 @@ impl ConnectionId {
     }
 }
 
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct UserId(Uuid);
 
@@ -60,3 +81,14 @@ impl DrawingId {
         Self(Uuid::new_v4())
     }
 }
+
+/// Identifies a finished game in the archive.
+/// A lobby can play several games, so this is separate from the [`GameId`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RecordId(pub Uuid);
+
+impl RecordId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
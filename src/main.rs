@@ -7,48 +7,293 @@ use axum::{
     Router,
     body::Bytes,
     extract::{
-        Path, State,
+        DefaultBodyLimit, Path, Query, State,
         ws::{WebSocket, WebSocketUpgrade},
     },
-    http::StatusCode,
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
     routing::{any, get, post},
 };
 use futures::stream::StreamExt;
-use std::{env, fs, sync::Arc};
+use serde::Deserialize;
+use std::{
+    env, fs,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::sync::Mutex;
 use tower_http::services::ServeDir;
+use tracing::Instrument;
 use uuid::Uuid;
 
 use crate::{
-    decrypto::settings::available_wordlists,
-    id::{ConnectionId, DrawingId, GameId},
+    archive::{Archive, read_record},
+    bot::{llm::LlmPlayer, model::AssociationModel},
+    config::{Config, Limits, LogFormat},
+    decrypto::{
+        GameInfo,
+        record::{ExportFormat, GameRecord},
+        registry::WordlistRegistry,
+        wordlist::parse_custom_wordlist,
+    },
+    drawing::{
+        Drawing, DrawingData, RenderOptions, check_drawing_owned, check_drawing_quota,
+        clear_spill_dir, render_drawing, sanitize_drawing,
+    },
+    id::{ConnectionId, DrawingId, GameId, RecordId, UserId, UserSecret},
     message::FromClient,
+    metrics::METRICS,
+    rate_limit::{RateLimiter, Verdict},
+    users::{load_users, save_users},
 };
 
+mod admin;
 mod app;
+mod archive;
+mod bot;
+mod config;
 mod decrypto;
+mod drawing;
 mod id;
 mod message;
+mod metrics;
+mod pairing;
+mod rate_limit;
+mod rating;
+mod stats;
+mod suggest;
+mod users;
+
+const DRAWING_COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+/// How often changed users are written to disk.
+const USERS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+/// How often to check whether all connections have closed during shutdown.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
-    let shared_state = Arc::new(Mutex::new(app::State::default()));
-    let static_files = ServeDir::new("./static");
-    let app = Router::new()
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(err) => {
+            eprintln!("Invalid configuration: {err}");
+            std::process::exit(2);
+        }
+    };
+    init_logging(config.log_format);
+
+    let wordlists = WordlistRegistry::load(&config.wordlist_dir);
+    if let Err(err) = config.default_settings.validate(&wordlists, None) {
+        eprintln!("Invalid configuration: default_settings: {err}");
+        std::process::exit(2);
+    }
+
+    if let Some(dir) = &config.drawing_dir {
+        match clear_spill_dir(dir) {
+            Ok(0) => {}
+            Ok(count) => tracing::info!("Removed {count} leftover drawings from {}", dir.display()),
+            Err(err) => {
+                eprintln!("Cannot clear drawing_dir {}: {err}", dir.display());
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let archive = match Archive::load(config.archive_dir()) {
+        Ok(archive) => archive,
+        Err(err) => {
+            eprintln!(
+                "Cannot load archive from {}: {err}",
+                config.archive_dir().display()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let users = match load_users(&config.users_path()) {
+        Ok(users) => users,
+        Err(err) => {
+            eprintln!(
+                "Cannot load users from {}: {err}",
+                config.users_path().display()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let bot_model = config
+        .bot_model
+        .as_ref()
+        .map(|path| match AssociationModel::load(path) {
+            Ok(model) => {
+                tracing::info!(
+                    "Loaded {} words for bots from {}",
+                    model.len(),
+                    path.display()
+                );
+                Arc::new(model)
+            }
+            Err(err) => {
+                eprintln!("Cannot load bot_model: {err}");
+                std::process::exit(1);
+            }
+        });
+    let llm_player = config.llm.clone().map(|llm| match LlmPlayer::new(llm) {
+        Ok(player) => Arc::new(player),
+        Err(err) => {
+            eprintln!("Cannot create LLM client: {err}");
+            std::process::exit(1);
+        }
+    });
+    let any_bots = bot_model.is_some() || llm_player.is_some();
+    let (new_bots_sender, new_bots) = tokio::sync::mpsc::unbounded_channel();
+
+    let shared_state = Arc::new_cyclic(|shared| {
+        Mutex::new(app::State::new(
+            config.clone(),
+            wordlists.clone(),
+            archive,
+            users,
+            bot_model.clone(),
+            any_bots.then_some(new_bots_sender),
+            shared.clone(),
+        ))
+    });
+    if any_bots {
+        tokio::spawn(bot::run(
+            shared_state.clone(),
+            bot_model,
+            llm_player,
+            new_bots,
+        ));
+    }
+    tokio::spawn(reload_wordlists(
+        shared_state.clone(),
+        wordlists,
+        Duration::from_secs(config.wordlist_poll_secs),
+    ));
+    tokio::spawn(collect_unused_drawings(shared_state.clone()));
+    tokio::spawn(save_users_periodically(shared_state.clone()));
+
+    let static_files = ServeDir::new(&config.static_dir);
+    let mut app = Router::new()
         .route("/ws", any(ws))
         .with_state(shared_state.clone())
         .route("/version", get(get_version))
+        .route("/metrics", get(get_metrics))
         .route("/wordlists", get(get_wordlists))
-        .route("/drawing/{game_id}/{drawing_id}", get(get_drawing))
-        .route("/drawing/{game_id}", post(post_drawing))
-        .with_state(shared_state)
-        .fallback_service(static_files);
-    let addr = std::env::args().nth(1).unwrap_or("0.0.0.0:3000".to_owned());
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    log::info!("Listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+        .with_state(shared_state.clone())
+        .route("/wordlist/{game_id}", post(post_wordlist))
+        .route("/games/history", get(get_history))
+        .route("/stats/{user_id}", get(get_stats))
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/games/{record_id}", get(get_record))
+        .route("/games/{record_id}/export", get(get_export))
+        .route(
+            "/drawing/{game_id}/{drawing_id}",
+            get(get_drawing)
+                .put(put_drawing)
+                .layer(DefaultBodyLimit::max(
+                    config.limits.max_drawing_bytes.saturating_add(1),
+                )),
+        )
+        .route(
+            "/drawing/{game_id}",
+            // Leave room over the limit so that too large drawings get a proper error.
+            post(post_drawing).layer(DefaultBodyLimit::max(
+                config.limits.max_drawing_bytes.saturating_add(1),
+            )),
+        )
+        .with_state(shared_state.clone());
+    if let Some(token) = &config.admin_token {
+        app = app.nest(
+            "/admin",
+            admin::router(token.expose().to_owned()).with_state(shared_state.clone()),
+        );
+    }
+    let app = app.fallback_service(static_files);
+    let listener = match tokio::net::TcpListener::bind(&config.bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Cannot listen on {}: {err}", config.bind);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Listening on {}", listener.local_addr().unwrap());
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+    shut_down(&shared_state).await;
+}
+
+/// Completes on SIGINT, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Should be able to listen for SIGINT");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Should be able to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down, no longer accepting connections");
+}
+
+/// Tell the connected clients that the server is restarting, wait for them to close their
+/// connections, and save the users. Games in progress are not kept.
+async fn shut_down(state: &Mutex<app::State>) {
+    let config = {
+        let mut state = metrics::lock(state).await;
+        let expected_downtime_secs = state.config.shutdown.expected_downtime_secs;
+        state.notify_shutdown(expected_downtime_secs).await;
+        state.config.clone()
+    };
+
+    let deadline = Instant::now() + config.shutdown.close_timeout();
+    loop {
+        let open = metrics::lock(state).await.websocket_count();
+        if open == 0 {
+            tracing::info!("All connections closed");
+            break;
+        }
+        if Instant::now() >= deadline {
+            tracing::warn!("Dropping {open} connections that did not close in time");
+            break;
+        }
+        tokio::time::sleep(SHUTDOWN_POLL_INTERVAL).await;
+    }
+
+    save_changed_users(state).await;
+    tracing::info!("Shutdown complete");
+}
+
+/// Log to stderr, filtered with `RUST_LOG` (`info` by default).
+/// The JSON format includes the fields of the enclosing spans, such as the connection and game.
+fn init_logging(format: LogFormat) {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
 }
 
 pub async fn get_version() -> Response {
@@ -66,93 +311,538 @@ pub async fn get_version() -> Response {
     .into_response()
 }
 
-pub async fn get_wordlists() -> Response {
-    let mut wordlists = available_wordlists();
+/// Server metrics in the Prometheus text format.
+async fn get_metrics(State(state): State<Arc<Mutex<app::State>>>) -> Response {
+    metrics::lock(&state).await.update_metrics(&METRICS);
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(),
+    )
+        .into_response()
+}
+
+pub async fn get_wordlists(State(state): State<Arc<Mutex<app::State>>>) -> Response {
+    axum::Json(metrics::lock(&state).await.wordlists.infos()).into_response()
+}
+
+/// Poll the wordlist directory every `poll_interval`, and swap in the new registry if any file
+/// changed. Polling is cheap, as only the modification times and sizes are compared.
+async fn reload_wordlists(
+    state: Arc<Mutex<app::State>>,
+    mut wordlists: WordlistRegistry,
+    poll_interval: Duration,
+) {
+    let mut interval = tokio::time::interval(poll_interval);
+    loop {
+        interval.tick().await;
+        let (changed, reloaded) = tokio::task::spawn_blocking(move || {
+            let changed = wordlists.reload();
+            (changed, wordlists)
+        })
+        .await
+        .expect("Wordlist reload should not panic");
+        wordlists = reloaded;
+
+        if changed {
+            metrics::lock(&state).await.wordlists = wordlists.clone();
+        }
+    }
+}
+
+/// Periodically remove drawings that were never used as a clue.
+async fn collect_unused_drawings(state: Arc<Mutex<app::State>>) {
+    let mut interval = tokio::time::interval(DRAWING_COLLECT_INTERVAL);
+    loop {
+        interval.tick().await;
+        metrics::lock(&state).await.collect_unused_drawings();
+    }
+}
+
+/// Periodically write the users to disk, if they changed.
+async fn save_users_periodically(state: Arc<Mutex<app::State>>) {
+    let mut interval = tokio::time::interval(USERS_SAVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        save_changed_users(&state).await;
+    }
+}
+
+/// Write the users to disk if they changed, without holding the state lock while writing.
+async fn save_changed_users(state: &Mutex<app::State>) {
+    // Keeps the periodic save and the one on shutdown from writing the same file at once.
+    static WRITING: Mutex<()> = Mutex::const_new(());
+    let _writing = WRITING.lock().await;
+
+    let (users, path) = {
+        let mut state = metrics::lock(state).await;
+        let Some(users) = state.take_users_to_save() else {
+            return;
+        };
+        (users, state.config.users_path())
+    };
+    if let Err(err) = save_users(&path, &users).await {
+        tracing::error!("Cannot save users: {err}");
+        // Try again next time.
+        metrics::lock(state).await.persist_users();
+    }
+}
+
+/// Upload a custom wordlist for a lobby, as plain text with one keyword per line.
+/// The text may start with a metadata header, see [`decrypto::wordlist::Wordlist::parse`].
+/// Requires the uploader to be in the lobby.
+async fn post_wordlist(
+    Path(game_id): Path<GameId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    let Some(secret) = bearer_secret(&headers) else {
+        return (StatusCode::UNAUTHORIZED, "Credentials required").into_response();
+    };
+
+    let mut state = metrics::lock(&state).await;
+    let Some(user_id) = state.find_client_by_secret(secret) else {
+        return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+    };
+
+    // Limited like the matching websocket message.
+    if state.check_message_rate(user_id, "set_custom_wordlist") != Verdict::Allow {
+        tracing::warn!("Rate limited wordlist upload from {user_id:?}");
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many wordlists uploaded, slow down",
+        )
+            .into_response();
+    }
+
+    if !state.games.contains_key(&game_id) {
+        tracing::warn!("Game {game_id:?} not found");
+        return (StatusCode::NOT_FOUND, "Game not found").into_response();
+    }
+
+    if !state.is_in_game(user_id, game_id) {
+        tracing::warn!("Rejected wordlist from {user_id:?}, not in game {game_id:?}");
+        return (StatusCode::FORBIDDEN, "You are not in this lobby").into_response();
+    }
+
+    // Only the text upload may have a header block.
+    let parse = |keyword_count| parse_custom_wordlist(&body, keyword_count);
+    match state.set_custom_wordlist(game_id, parse).await {
+        Ok(count) => (StatusCode::OK, count.to_string()).into_response(),
+        Err(err) => {
+            tracing::warn!("Rejected wordlist for game {game_id:?}: {err}");
+            (StatusCode::BAD_REQUEST, err).into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct HistoryQuery {
+    /// Only games this user played in.
+    player: Option<UserId>,
+    offset: usize,
+    limit: usize,
+}
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            player: None,
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+const MAX_LIST_LIMIT: usize = 200;
+
+/// List archived games, newest first.
+async fn get_history(
+    Query(query): Query<HistoryQuery>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let state = metrics::lock(&state).await;
+    let games = state
+        .archive
+        .history(query.player, query.offset, query.limit.min(MAX_LIST_LIMIT));
+    axum::Json(games).into_response()
+}
+
+/// Statistics of a user over all archived games.
+async fn get_stats(
+    Path(user_id): Path<UserId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    axum::Json(metrics::lock(&state).await.archive.stats(user_id)).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct LeaderboardQuery {
+    limit: usize,
+}
+impl Default for LeaderboardQuery {
+    fn default() -> Self {
+        Self { limit: 50 }
+    }
+}
+
+/// Players with the highest ratings, from all archived games.
+async fn get_leaderboard(
+    Query(query): Query<LeaderboardQuery>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let state = metrics::lock(&state).await;
+    let leaderboard = state
+        .archive
+        .ratings()
+        .leaderboard(query.limit.min(MAX_LIST_LIMIT));
+    axum::Json(leaderboard).into_response()
+}
+
+/// Read a game from the archive.
+async fn load_record(
+    state: &Mutex<app::State>,
+    record_id: RecordId,
+) -> Result<GameRecord, Response> {
+    let Some(path) = metrics::lock(state).await.archive.record_path(record_id) else {
+        tracing::warn!("Archived game {record_id:?} not found");
+        return Err((StatusCode::NOT_FOUND, "Game not found").into_response());
+    };
+
+    read_record(&path).await.map_err(|err| {
+        tracing::error!("Cannot read archived game {record_id:?}: {err}");
+        (StatusCode::INTERNAL_SERVER_ERROR, "Cannot read game").into_response()
+    })
+}
 
-    wordlists.sort();
-    if let Some(i) = wordlists.iter().position(|s| s == "original") {
-        wordlists.swap(0, i);
+/// Full record of an archived game.
+async fn get_record(
+    Path(record_id): Path<RecordId>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    match load_record(&state, record_id).await {
+        Ok(record) => axum::Json(record).into_response(),
+        Err(response) => response,
     }
+}
 
-    axum::Json(serde_json::json!(wordlists)).into_response()
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Export an archived game, as `?format=json` (default), `markdown` or `html`.
+async fn get_export(
+    Path(record_id): Path<RecordId>,
+    Query(ExportQuery { format }): Query<ExportQuery>,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    match load_record(&state, record_id).await {
+        Ok(record) => (
+            [(header::CONTENT_TYPE, format.content_type())],
+            record.render(format),
+        )
+            .into_response(),
+        Err(response) => response,
+    }
 }
 
+/// Serve a drawing, see [`RenderOptions`] for the query parameters.
 async fn get_drawing(
     Path((game_id, drawing_id)): Path<(GameId, DrawingId)>,
+    Query(options): Query<RenderOptions>,
     State(state): State<Arc<Mutex<app::State>>>,
 ) -> Response {
-    let state = state.lock().await;
-    let Some(game) = state.games.get(&game_id) else {
-        log::warn!("Game {game_id:?} not found");
-        return (StatusCode::NOT_FOUND, "Game not found").into_response();
+    let (format, data, config) = {
+        let state = metrics::lock(&state).await;
+        let Some(game) = state.games.get(&game_id) else {
+            tracing::warn!("Game {game_id:?} not found");
+            return (StatusCode::NOT_FOUND, "Game not found").into_response();
+        };
+
+        let Some(drawing) = game.drawings.get(&drawing_id) else {
+            tracing::warn!("Drawing {drawing_id:?} not found in game {game_id:?}");
+            return (StatusCode::NOT_FOUND, "Drawing not found").into_response();
+        };
+        (drawing.format, drawing.data.clone(), state.config.clone())
     };
 
-    let Some(drawing) = game.drawings.get(&drawing_id) else {
-        log::warn!("Drawing {drawing_id:?} not found in game {game_id:?}");
-        return (StatusCode::NOT_FOUND, "Drawing not found").into_response();
+    let bytes = match data.load().await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!("Cannot read drawing {drawing_id:?}: {err}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Cannot read drawing").into_response();
+        }
+    };
+
+    let rendered = tokio::task::spawn_blocking(move || {
+        let limits = &config.limits;
+        render_drawing(
+            format,
+            bytes,
+            &options,
+            limits.max_drawing_width,
+            limits.max_drawing_height,
+        )
+    })
+    .await
+    .expect("Drawing rendering should not panic");
+    let (content_type, bytes) = match rendered {
+        Ok(rendered) => rendered,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
     };
 
     axum::response::Response::builder()
-        .header("Content-Type", "image/png")
-        .body(axum::body::Body::from(drawing.clone()))
+        .header("Content-Type", content_type)
+        .body(axum::body::Body::from(bytes))
         .unwrap()
         .into_response()
 }
 
+/// Reads the user secret from an `Authorization: Bearer <secret>` header.
+fn bearer_secret(headers: &HeaderMap) -> Option<UserSecret> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Upload a drawing, either a png or strokes as JSON. Requires the uploader to be the current encryptor in the game.
 async fn post_drawing(
     Path(game_id): Path<GameId>,
     State(state): State<Arc<Mutex<app::State>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    upload_drawing(state, game_id, None, headers, body).await
+}
+
+/// Replace a drawing uploaded earlier by the same user, if it hasn't been used as a clue yet.
+async fn put_drawing(
+    Path((game_id, drawing_id)): Path<(GameId, DrawingId)>,
+    State(state): State<Arc<Mutex<app::State>>>,
+    headers: HeaderMap,
     body: Bytes,
 ) -> Response {
-    let mut state = state.lock().await;
+    upload_drawing(state, game_id, Some(drawing_id), headers, body).await
+}
+
+async fn upload_drawing(
+    state: Arc<Mutex<app::State>>,
+    game_id: GameId,
+    replace: Option<DrawingId>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(secret) = bearer_secret(&headers) else {
+        return (StatusCode::UNAUTHORIZED, "Credentials required").into_response();
+    };
+
+    let check = |game: &GameInfo, user_id, limits: &Limits| {
+        game.check_drawing_upload(user_id)?;
+        match replace {
+            Some(drawing_id) => check_drawing_owned(&game.drawings, user_id, drawing_id),
+            None => check_drawing_quota(&game.drawings, user_id, limits),
+        }
+    };
+
+    let (user_id, config) = {
+        let mut state = metrics::lock(&state).await;
+        let Some(user_id) = state.find_client_by_secret(secret) else {
+            return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+        };
+
+        if state.check_drawing_upload(user_id) != Verdict::Allow {
+            tracing::warn!("Rate limited drawing upload from {user_id:?}");
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many drawings uploaded, slow down",
+            )
+                .into_response();
+        }
+
+        let Some(game) = state.games.get(&game_id) else {
+            tracing::warn!("Game {game_id:?} not found");
+            return (StatusCode::NOT_FOUND, "Game not found").into_response();
+        };
+
+        if let Err(err) = check(game, user_id, &state.config.limits) {
+            tracing::warn!("Rejected drawing from {user_id:?} in game {game_id:?}: {err}");
+            return (StatusCode::FORBIDDEN, err).into_response();
+        }
+
+        (user_id, state.config.clone())
+    };
+
+    let limits = &config.limits;
+    if body.len() > limits.max_drawing_bytes {
+        tracing::warn!("Drawing too large");
+        return (StatusCode::PAYLOAD_TOO_LARGE, "Drawing too large").into_response();
+    }
+
+    // Decoding and writing to disk are slow, so do them without holding the lock.
+    let id = replace.unwrap_or_else(DrawingId::new);
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let stored = tokio::task::spawn_blocking({
+        let config = config.clone();
+        move || {
+            let limits = &config.limits;
+            let (format, bytes) = sanitize_drawing(
+                content_type.as_deref(),
+                &body,
+                limits.max_drawing_width,
+                limits.max_drawing_height,
+            )
+            .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+            let data =
+                DrawingData::store(bytes, id, config.drawing_dir.as_deref()).map_err(|err| {
+                    tracing::error!("Cannot store drawing: {err}");
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Cannot store drawing".to_owned(),
+                    )
+                })?;
+            Ok((format, data))
+        }
+    })
+    .await
+    .expect("Drawing decoding should not panic");
+    let (format, data) = match stored {
+        Ok(stored) => stored,
+        Err((status, err)) => {
+            tracing::warn!("Rejected drawing from {user_id:?} in game {game_id:?}: {err}");
+            return (status, err).into_response();
+        }
+    };
+
+    let mut state = metrics::lock(&state).await;
     let Some(game) = state.games.get_mut(&game_id) else {
-        log::warn!("Game {game_id:?} not found");
+        tracing::warn!("Game {game_id:?} not found");
         return (StatusCode::NOT_FOUND, "Game not found").into_response();
     };
 
-    if body.len() > 1024 * 1024 {
-        log::warn!("Drawing too large");
-        return (StatusCode::PAYLOAD_TOO_LARGE, "Drawing too large").into_response();
+    // The round might have progressed, or other drawings been uploaded, while decoding.
+    if let Err(err) = check(game, user_id, &config.limits) {
+        tracing::warn!("Rejected drawing from {user_id:?} in game {game_id:?}: {err}");
+        return (StatusCode::FORBIDDEN, err).into_response();
     }
 
-    let id = DrawingId::new();
-    game.drawings.insert(id, body.to_vec());
+    game.drawings.insert(
+        id,
+        Drawing {
+            uploader: user_id,
+            format,
+            data,
+            uploaded_at: Instant::now(),
+            locked: false,
+        },
+    );
 
     (StatusCode::OK, id.0.to_string()).into_response()
 }
 
-async fn ws(ws: WebSocketUpgrade, State(state): State<Arc<Mutex<app::State>>>) -> Response {
-    ws.on_upgrade(move |socket| handle_socket(socket, state))
+async fn ws(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    State(state): State<Arc<Mutex<app::State>>>,
+) -> Response {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok());
+    let device = app::device_name(user_agent);
+    ws.on_upgrade(move |socket| handle_socket(socket, state, device))
 }
 
-async fn handle_socket(socket: WebSocket, state: Arc<Mutex<app::State>>) {
+async fn handle_socket(socket: WebSocket, state: Arc<Mutex<app::State>>, device: String) {
     let id = ConnectionId::new();
-    log::info!("Client {id:?} connected");
+    let span = tracing::info_span!("connection", connection_id = %id, device = %device);
+    serve_socket(socket, state, id, device)
+        .instrument(span)
+        .await
+}
+
+async fn serve_socket(
+    socket: WebSocket,
+    state: Arc<Mutex<app::State>>,
+    id: ConnectionId,
+    device: String,
+) {
+    tracing::info!("Client connected");
 
     let (sender, mut receiver) = socket.split();
-    {
-        let mut state = state.lock().await;
-        state.on_connect(id, sender).await;
-    }
+    let config = {
+        let mut state = metrics::lock(&state).await;
+        state.on_connect(id, sender, device).await;
+        state.config.clone()
+    };
+    // Checked before taking the lock, so that flooding doesn't hold up everyone else.
+    let mut rate_limiter = RateLimiter::default();
+
+    let mut heartbeat = tokio::time::interval(config.heartbeat.interval());
+    heartbeat.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick completes right away.
+    heartbeat.tick().await;
+    let mut last_seen = Instant::now();
+
+    loop {
+        let msg = tokio::select! {
+            msg = receiver.next() => msg,
+            _ = heartbeat.tick() => {
+                // Half-open connections don't error for a long time, so time them out instead.
+                if last_seen.elapsed() > config.heartbeat.timeout() {
+                    tracing::info!("Closing unresponsive connection");
+                    break;
+                }
+                metrics::lock(&state).await.send_ping(id).await;
+                continue;
+            }
+        };
+        let Some(msg) = msg else {
+            break;
+        };
+        last_seen = Instant::now();
 
-    while let Some(msg) = receiver.next().await {
         let Ok(msg) = msg else {
-            log::warn!("Error receiving message or client disconnected");
+            tracing::warn!("Error receiving message or client disconnected");
             // client disconnected, or perhaps an error occurred
             break;
         };
 
         match msg {
             axum::extract::ws::Message::Text(text) => {
-                log::debug!("Received text message: {}", text);
                 match serde_json::from_str::<FromClient>(&text) {
                     Ok(payload) => {
-                        let _ = state.lock().await.on_message(id, payload).await;
+                        let verdict =
+                            rate_limiter.check_message(&config.rate_limits, payload.kind());
+                        match verdict {
+                            Verdict::Drop => continue,
+                            Verdict::Disconnect => {
+                                tracing::warn!(
+                                    "Disconnecting client that keeps exceeding the rate limits"
+                                );
+                                break;
+                            }
+                            Verdict::Allow | Verdict::Warn => {}
+                        }
+                        let mut state = metrics::lock(&state).await;
+                        if state.enforce_rate_limit(id, verdict).await.is_ok() {
+                            let _ = state.on_message(id, payload).await;
+                        }
                     }
                     Err(_) => {
-                        log::warn!("Failed to parse message: {}", text);
-                        let _ = state
-                            .lock()
+                        // The text may hold a secret, so only its size is logged.
+                        tracing::warn!(bytes = text.len(), "Failed to parse message");
+                        METRICS.parse_failures.inc();
+                        let _ = metrics::lock(&state)
                             .await
                             .send_to_connection(
                                 id,
@@ -167,17 +857,17 @@ async fn handle_socket(socket: WebSocket, state: Arc<Mutex<app::State>>) {
                 }
             }
             axum::extract::ws::Message::Binary(_) => {
-                log::warn!("Received binary message, not supported");
+                tracing::warn!("Received binary message, not supported");
                 break;
             }
             axum::extract::ws::Message::Close(_) => {
-                log::info!("Client disconnected");
+                tracing::info!("Client disconnected");
                 break;
             }
             _ => {}
         }
     }
 
-    let mut state = state.lock().await;
+    let mut state = metrics::lock(&state).await;
     state.on_disconnect(id).await;
 }
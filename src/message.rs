@@ -5,11 +5,17 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
+    bot::BotKind,
     decrypto::{
         Code, PerTeam, Role, Round, RoundPerTeam, RoundResult, Team, TiebreakerRound, TimedOut,
-        check_tiebreaker_guess, settings::GameSettings,
+        check_tiebreaker_guess,
+        record::ExportFormat,
+        settings::GameSettings,
+        wordlist::{Language, WordlistInfo},
     },
-    id::{GameId, UserId, UserSecret},
+    drawing::DrawingFormat,
+    id::{DrawingId, GameId, RecordId, UserId, UserSecret},
+    stats::PlayerStats,
 };
 
 #[derive(Debug, Clone, Deserialize)]
@@ -18,14 +24,39 @@ pub enum FromClient {
     Auth {
         secret: Option<UserSecret>,
     },
+    /// Ask for a code to log in as this user on another device, answered with [`ToClient::PairingCode`].
+    RequestPairingCode {
+        /// Log out this and the user's other devices once the code is used.
+        #[serde(default)]
+        revoke_others: bool,
+    },
+    /// Log in as the user that requested the code, instead of [`FromClient::Auth`].
+    RedeemPairingCode(String),
     SetNick(String),
     CreateLobby,
     JoinLobby(GameId),
     LeaveLobby,
     JoinTeam(Team),
+    /// Kick a disconnected player, or remove a bot.
     Kick(UserId),
-    ChangeSettings(GameSettings),
+    /// Add a bot player to a team, in the lobby.
+    AddBot {
+        team: Team,
+        #[serde(flatten)]
+        kind: BotKind,
+    },
+    /// Replace the lobby settings. Every field must be given.
+    ChangeSettings(#[serde(deserialize_with = "GameSettings::deserialize_complete")] GameSettings),
+    /// Upload a keyword list for this lobby, one keyword per entry.
+    /// Select it with the `"custom"` wordlist in the settings.
+    SetCustomWordlist(Vec<String>),
     StartGame,
+    /// Move a finished game back to the lobby to play again.
+    ReturnToLobby,
+    /// Forget the keywords used in earlier games, so they can be picked again.
+    ClearKeywordHistory,
+    /// Request a record of the finished game, answered with [`ToClient::Export`].
+    ExportGame(ExportFormat),
     SubmitClues(Vec<Clue>),
     SubmitDecipher(Code),
     SubmitIntercept(Code),
@@ -33,6 +64,15 @@ pub enum FromClient {
         index: usize,
         guess: String,
     },
+    /// Ask for the most likely codes for the current round's clues, if the game allows it.
+    /// Answered with [`ToClient::Suggestions`].
+    RequestSuggestions {
+        /// Suggest codes of the other team instead of the own team.
+        intercept: bool,
+        /// Number of codes, 5 if not given.
+        #[serde(default)]
+        count: Option<usize>,
+    },
     TriggerTimers,
     Frustrated {
         /// Is this about guessing or encrypting?
@@ -43,6 +83,66 @@ pub enum FromClient {
     GlobalChat(String),
 }
 
+impl FromClient {
+    /// All values of [`Self::kind`].
+    pub const KINDS: &[&str] = &[
+        "auth",
+        "request_pairing_code",
+        "redeem_pairing_code",
+        "set_nick",
+        "create_lobby",
+        "join_lobby",
+        "leave_lobby",
+        "join_team",
+        "kick",
+        "add_bot",
+        "change_settings",
+        "set_custom_wordlist",
+        "start_game",
+        "return_to_lobby",
+        "clear_keyword_history",
+        "export_game",
+        "submit_clues",
+        "submit_decipher",
+        "submit_intercept",
+        "submit_tiebreaker",
+        "request_suggestions",
+        "trigger_timers",
+        "frustrated",
+        "global_chat",
+    ];
+
+    /// Name of the message type, as in the JSON.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Auth { .. } => "auth",
+            Self::RequestPairingCode { .. } => "request_pairing_code",
+            Self::RedeemPairingCode(_) => "redeem_pairing_code",
+            Self::SetNick(_) => "set_nick",
+            Self::CreateLobby => "create_lobby",
+            Self::JoinLobby(_) => "join_lobby",
+            Self::LeaveLobby => "leave_lobby",
+            Self::JoinTeam(_) => "join_team",
+            Self::Kick(_) => "kick",
+            Self::AddBot { .. } => "add_bot",
+            Self::ChangeSettings(_) => "change_settings",
+            Self::SetCustomWordlist(_) => "set_custom_wordlist",
+            Self::StartGame => "start_game",
+            Self::ReturnToLobby => "return_to_lobby",
+            Self::ClearKeywordHistory => "clear_keyword_history",
+            Self::ExportGame(_) => "export_game",
+            Self::SubmitClues(_) => "submit_clues",
+            Self::SubmitDecipher(_) => "submit_decipher",
+            Self::SubmitIntercept(_) => "submit_intercept",
+            Self::SubmitTiebreaker { .. } => "submit_tiebreaker",
+            Self::RequestSuggestions { .. } => "request_suggestions",
+            Self::TriggerTimers => "trigger_timers",
+            Self::Frustrated { .. } => "frustrated",
+            Self::GlobalChat(_) => "global_chat",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ToClient {
@@ -54,6 +154,51 @@ pub enum ToClient {
         message: String,
         severity: ErrorSeverity,
     },
+    Export {
+        format: ExportFormat,
+        content: String,
+    },
+    Suggestions {
+        intercept: bool,
+        /// Number of completed rounds when the suggestions were made.
+        round: usize,
+        /// Most likely codes first.
+        suggestions: Vec<CodeSuggestion>,
+    },
+    /// Code for [`FromClient::RedeemPairingCode`] on another device.
+    PairingCode {
+        code: String,
+        expires_in_secs: u64,
+        /// Whether the user's current devices are logged out once the code is used.
+        revoke_others: bool,
+    },
+    /// The server is shutting down and about to close the connection.
+    ServerRestarting {
+        /// How long until the server is expected to be back, if known.
+        expected_downtime_secs: Option<u64>,
+    },
+}
+
+impl ToClient {
+    /// Name of the message type, as in the JSON.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::State { .. } => "state",
+            Self::Error { .. } => "error",
+            Self::Export { .. } => "export",
+            Self::Suggestions { .. } => "suggestions",
+            Self::PairingCode { .. } => "pairing_code",
+            Self::ServerRestarting { .. } => "server_restarting",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CodeSuggestion {
+    pub code: Code,
+    /// Estimated chance that this is the code, from 0 to 1.
+    pub probability: f64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -62,22 +207,41 @@ pub struct UserInfo {
     pub id: UserId,
     pub secret: UserSecret,
     pub nick: Option<String>,
+    /// Statistics over all archived games the user played.
+    pub stats: PlayerStats,
+    /// Devices the user is connected from, oldest connection first.
+    pub devices: Vec<DeviceInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DeviceInfo {
+    /// Browser and operating system, such as `Firefox on Android`.
+    pub name: String,
+    /// Whether this is the connection the message was sent to.
+    pub current: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct PlayerInfo {
     pub id: UserId,
-    /// Whether the player is connected to the game.
+    /// Whether the player is connected to the game, from any device.
     pub connected: bool,
+    /// Number of devices the player is connected from.
+    pub devices: usize,
     /// If the player is still in the game lobby.
     /// Note that a disconnected player is still considerent present if they have not been kicked.
     /// If `false`, the player has been kicked or has left the game otherwise.
     pub is_in_game: bool,
+    /// Bots are played by the server.
+    pub is_bot: bool,
     //// Nickname of the player.
     pub nick: String,
     /// None for the players that have not joined a team yet, or have been kicked.
     pub team: Option<Team>,
+    /// Rating from all archived games.
+    pub rating: f64,
 }
 
 /// Game view from the perspective of single user.
@@ -86,6 +250,8 @@ pub struct PlayerInfo {
 pub struct GameView {
     pub id: GameId,
     pub settings: GameSettings,
+    /// Metadata of the custom wordlist, if one has been uploaded.
+    pub custom_wordlist: Option<WordlistInfo>,
     /// All players that have ever been in this game.
     pub players: Vec<PlayerInfo>,
     pub global_chat: Vec<ChatMessage>,
@@ -98,6 +264,14 @@ pub struct GameView {
 pub enum GameStateView {
     Lobby {
         reason_not_startable: Option<String>,
+        /// Keywords used in earlier games in this lobby.
+        /// They will not be picked again until the wordlists run out.
+        keyword_history: Vec<String>,
+        /// Which kinds of bots can be added to the game.
+        association_bots_available: bool,
+        llm_bots_available: bool,
+        /// Split of the players in the lobby with the closest average ratings.
+        suggested_teams: Option<PerTeam<Vec<UserId>>>,
     },
     InGame {
         /// Complete rounds (public info).
@@ -115,6 +289,9 @@ pub enum GameStateView {
         keywords: PerTeam<Vec<String>>,
         completed_rounds: Vec<PerTeam<CompletedRoundPerTeam>>,
         tiebreaker: Option<TiebreakerRound>,
+        /// Id of the game in the archive, for sharing and exporting it.
+        /// `None` until it has been archived.
+        record_id: Option<RecordId>,
     },
     /// The game is in progress, but you're not in a team.
     InGameNotInTeam,
@@ -169,14 +346,29 @@ pub enum ErrorSeverity {
 #[serde(rename_all = "snake_case")]
 pub enum Clue {
     Text(String),
-    Drawing(Uuid),
+    /// Png drawing.
+    Drawing(DrawingId),
+    /// Drawing uploaded as strokes, which can also be served as svg or replayed.
+    Strokes(DrawingId),
+}
+
+impl Clue {
+    /// The drawing the clue refers to, and the format it must have been uploaded in.
+    pub fn drawing(&self) -> Option<(DrawingId, DrawingFormat)> {
+        match self {
+            Clue::Text(_) => None,
+            Clue::Drawing(id) => Some((*id, DrawingFormat::Png)),
+            Clue::Strokes(id) => Some((*id, DrawingFormat::Strokes)),
+        }
+    }
 }
 
 impl fmt::Display for Clue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Clue::Text(text) => write!(f, "<clue:text:{text}>"),
-            Clue::Drawing(id) => write!(f, "<clue:drawing:{id}>"),
+            Clue::Drawing(id) => write!(f, "<clue:drawing:{}>", id.0),
+            Clue::Strokes(id) => write!(f, "<clue:strokes:{}>", id.0),
         }
     }
 }
@@ -222,11 +414,11 @@ pub struct TiebreakerInputSubmission {
     pub is_correct: bool,
 }
 impl TiebreakerInputSubmission {
-    pub fn new(guess: &str, correct: &str) -> Self {
+    pub fn new(guess: &str, correct: &str, language: &Language) -> Self {
         Self {
             guess: guess.to_owned(),
             correct: correct.to_owned(),
-            is_correct: check_tiebreaker_guess(guess, correct),
+            is_correct: check_tiebreaker_guess(guess, correct, language),
         }
     }
 }
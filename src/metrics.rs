@@ -0,0 +1,203 @@
+use std::{sync::LazyLock, time::Instant};
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{app, decrypto::Team, message::FromClient};
+
+/// Longest error category label, to keep the number of label values in check.
+const MAX_CATEGORY_LEN: usize = 48;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Server metrics in the Prometheus format, served at `/metrics`.
+/// Gauges are refreshed from the state when scraped, counters and histograms as things happen.
+pub struct Metrics {
+    registry: Registry,
+    pub connected_clients: IntGauge,
+    pub authenticated_users: IntGauge,
+    pub games: IntGaugeVec,
+    pub drawing_bytes: IntGauge,
+    pub messages: IntCounterVec,
+    pub parse_failures: IntCounter,
+    pub errors_sent: IntCounterVec,
+    pub lock_wait_seconds: Histogram,
+    pub game_duration_seconds: Histogram,
+    pub games_finished: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("decrypto".to_owned()), None)
+            .expect("Prefix should be valid");
+        let metrics = Self {
+            connected_clients: IntGauge::new(
+                "connected_clients",
+                "Open websocket connections. Bots play in the process, without one.",
+            )
+            .unwrap(),
+            authenticated_users: IntGauge::new(
+                "authenticated_users",
+                "Distinct users logged in on at least one connection, bots included.",
+            )
+            .unwrap(),
+            games: IntGaugeVec::new(Opts::new("games", "Games by state."), &["state"]).unwrap(),
+            drawing_bytes: IntGauge::new(
+                "drawing_bytes",
+                "Size of the stored drawings, in memory or on disk.",
+            )
+            .unwrap(),
+            messages: IntCounterVec::new(
+                Opts::new("messages_total", "Messages handled, by type."),
+                &["type"],
+            )
+            .unwrap(),
+            parse_failures: IntCounter::new(
+                "parse_failures_total",
+                "Websocket messages that could not be parsed.",
+            )
+            .unwrap(),
+            errors_sent: IntCounterVec::new(
+                Opts::new("errors_sent_total", "Errors sent to clients, by category."),
+                &["category"],
+            )
+            .unwrap(),
+            lock_wait_seconds: Histogram::with_opts(
+                HistogramOpts::new(
+                    "lock_wait_seconds",
+                    "Time spent waiting for the shared state lock.",
+                )
+                .buckets(vec![
+                    0.000_01, 0.000_1, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+                ]),
+            )
+            .unwrap(),
+            game_duration_seconds: Histogram::with_opts(
+                HistogramOpts::new(
+                    "game_duration_seconds",
+                    "Time from the start of a game to its end.",
+                )
+                .buckets(vec![
+                    60.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 2700.0, 3600.0, 7200.0,
+                ]),
+            )
+            .unwrap(),
+            games_finished: IntCounterVec::new(
+                Opts::new("games_finished_total", "Finished games, by outcome."),
+                &["outcome"],
+            )
+            .unwrap(),
+            registry,
+        };
+
+        for collector in [
+            Box::new(metrics.connected_clients.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(metrics.authenticated_users.clone()),
+            Box::new(metrics.games.clone()),
+            Box::new(metrics.drawing_bytes.clone()),
+            Box::new(metrics.messages.clone()),
+            Box::new(metrics.parse_failures.clone()),
+            Box::new(metrics.errors_sent.clone()),
+            Box::new(metrics.lock_wait_seconds.clone()),
+            Box::new(metrics.game_duration_seconds.clone()),
+            Box::new(metrics.games_finished.clone()),
+        ] {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Metric names should be unique");
+        }
+        metrics
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Encoding to a Vec should not fail");
+        String::from_utf8(buffer).expect("Metrics should be UTF-8")
+    }
+
+    pub fn message_handled(&self, msg: &FromClient) {
+        self.messages.with_label_values(&[msg.kind()]).inc();
+    }
+
+    pub fn error_sent(&self, message: &str) {
+        self.errors_sent
+            .with_label_values(&[&error_category(message)])
+            .inc();
+    }
+
+    /// A game ended. `started_at` is `None` if unknown.
+    pub fn game_finished(&self, outcome: GameOutcome, started_at: Option<Instant>) {
+        let outcome = match outcome {
+            GameOutcome::Won(team) if team == Team::WHITE => "white_won",
+            GameOutcome::Won(_) => "black_won",
+            GameOutcome::Draw => "draw",
+            GameOutcome::Aborted => "aborted",
+        };
+        self.games_finished.with_label_values(&[outcome]).inc();
+        if let Some(started_at) = started_at {
+            self.game_duration_seconds
+                .observe(started_at.elapsed().as_secs_f64());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GameOutcome {
+    Won(Team),
+    Draw,
+    /// Ended by an administrator.
+    Aborted,
+}
+
+/// Locks the shared state, recording how long it took.
+pub async fn lock(state: &Mutex<app::State>) -> MutexGuard<'_, app::State> {
+    let start = Instant::now();
+    let guard = state.lock().await;
+    METRICS
+        .lock_wait_seconds
+        .observe(start.elapsed().as_secs_f64());
+    guard
+}
+
+/// Groups error messages that differ only in their details, such as
+/// `Invalid settings: ...` or `Wordlist 'x' does not exist`, by the text before them.
+fn error_category(message: &str) -> String {
+    let end = message
+        .find([':', '\'', '"', '<', '('])
+        .unwrap_or(message.len());
+    let mut category = message[..end].trim().to_owned();
+    if category.len() > MAX_CATEGORY_LEN {
+        let mut cut = MAX_CATEGORY_LEN;
+        while !category.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        category.truncate(cut);
+    }
+    category
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_categories_drop_details() {
+        assert_eq!(
+            error_category("Invalid settings: round_limit must be positive"),
+            "Invalid settings"
+        );
+        assert_eq!(
+            error_category("Keyword 'apple' is listed more than once"),
+            "Keyword"
+        );
+        assert_eq!(error_category("Game not found"), "Game not found");
+        assert_eq!(error_category(&"x".repeat(100)).len(), MAX_CATEGORY_LEN);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use rand::seq::IndexedRandom;
+
+use crate::id::UserId;
+
+/// Letters and digits that are hard to confuse with each other when typed from another screen.
+const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LEN: usize = 8;
+
+/// Short-lived, single use codes for logging in as an existing user on another device.
+#[derive(Debug, Default)]
+pub struct PairingCodes {
+    codes: HashMap<String, Pairing>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Pairing {
+    pub user_id: UserId,
+    /// Log out the user's other devices once the code is redeemed.
+    pub revoke_others: bool,
+    expires_at: Instant,
+}
+
+impl PairingCodes {
+    /// A new code for the user, replacing any earlier one.
+    pub fn create(&mut self, user_id: UserId, revoke_others: bool, ttl: Duration) -> String {
+        let now = Instant::now();
+        self.codes
+            .retain(|_, pairing| pairing.user_id != user_id && pairing.expires_at > now);
+        let code = loop {
+            let code = generate_code();
+            if !self.codes.contains_key(&code) {
+                break code;
+            }
+        };
+        self.codes.insert(
+            code.clone(),
+            Pairing {
+                user_id,
+                revoke_others,
+                expires_at: now + ttl,
+            },
+        );
+        code
+    }
+
+    /// Use up a code. Case, spaces and dashes are ignored.
+    pub fn redeem(&mut self, code: &str) -> Option<Pairing> {
+        self.codes
+            .remove(&normalize_code(code))
+            .filter(|pairing| pairing.expires_at > Instant::now())
+    }
+
+    /// Forget the codes of a user, for example when they are banned.
+    pub fn revoke(&mut self, user_id: UserId) {
+        self.codes.retain(|_, pairing| pairing.user_id != user_id);
+    }
+}
+
+fn generate_code() -> String {
+    let mut rng = rand::rng();
+    (0..CODE_LEN)
+        .map(|_| *ALPHABET.choose(&mut rng).expect("Alphabet is not empty") as char)
+        .collect()
+}
+
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_single_use_and_expire() {
+        let mut codes = PairingCodes::default();
+        let user_id = UserId::new();
+        let ttl = Duration::from_secs(60);
+
+        let code = codes.create(user_id, true, ttl);
+        assert_eq!(code.len(), CODE_LEN);
+        let typed = format!("{}-{}", &code[..4], &code[4..]).to_lowercase();
+        let pairing = codes.redeem(&typed).unwrap();
+        assert_eq!(pairing.user_id, user_id);
+        assert!(pairing.revoke_others);
+        assert!(codes.redeem(&code).is_none());
+
+        // A new code replaces the earlier one.
+        let first = codes.create(user_id, false, ttl);
+        let second = codes.create(user_id, false, ttl);
+        assert!(codes.redeem(&first).is_none());
+        assert!(codes.redeem(&second).is_some());
+
+        let expired = codes.create(user_id, false, Duration::ZERO);
+        assert!(codes.redeem(&expired).is_none());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::config::{RateLimit, RateLimits};
+
+/// What to do with an action after checking it against the limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Drop the action and warn the client. Only the first dropped action in a window warns.
+    Warn,
+    /// Drop the action silently.
+    Drop,
+    /// Too many dropped actions, disconnect the client.
+    Disconnect,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn try_take(&mut self, limit: RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        self.updated_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Token buckets of a single connection or user, one for each kind of action.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+    /// Actions dropped since `window_start`.
+    violations: u32,
+    window_start: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Check a message of the given [`FromClient::kind`](crate::message::FromClient::kind).
+    pub fn check_message(&mut self, limits: &RateLimits, kind: &'static str) -> Verdict {
+        self.check(limits, kind, limits.for_message(kind), Instant::now())
+    }
+
+    pub fn check_drawing_upload(&mut self, limits: &RateLimits) -> Verdict {
+        self.check(
+            limits,
+            "drawing_upload",
+            limits.drawing_upload,
+            Instant::now(),
+        )
+    }
+
+    fn check(
+        &mut self,
+        limits: &RateLimits,
+        kind: &'static str,
+        limit: RateLimit,
+        now: Instant,
+    ) -> Verdict {
+        let bucket = self
+            .buckets
+            .entry(kind)
+            .or_insert_with(|| TokenBucket::new(limit, now));
+        if bucket.try_take(limit, now) {
+            return Verdict::Allow;
+        }
+
+        let window = Duration::from_secs(limits.violation_window_secs);
+        if self
+            .window_start
+            .is_none_or(|start| now.saturating_duration_since(start) > window)
+        {
+            self.window_start = Some(now);
+            self.violations = 0;
+        }
+        self.violations += 1;
+
+        if self.violations > limits.max_violations {
+            Verdict::Disconnect
+        } else if self.violations == 1 {
+            Verdict::Warn
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_are_allowed_then_refilled() {
+        let limits = RateLimits {
+            max_violations: 3,
+            ..RateLimits::default()
+        };
+        let limit = RateLimit::new(2, 1.0);
+        let mut limiter = RateLimiter::default();
+        let start = Instant::now();
+
+        assert_eq!(limiter.check(&limits, "a", limit, start), Verdict::Allow);
+        assert_eq!(limiter.check(&limits, "a", limit, start), Verdict::Allow);
+        assert_eq!(limiter.check(&limits, "a", limit, start), Verdict::Warn);
+        // Kinds have separate buckets.
+        assert_eq!(limiter.check(&limits, "b", limit, start), Verdict::Allow);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.check(&limits, "a", limit, later), Verdict::Allow);
+        assert_eq!(limiter.check(&limits, "a", limit, later), Verdict::Drop);
+        assert_eq!(limiter.check(&limits, "a", limit, later), Verdict::Drop);
+        assert_eq!(
+            limiter.check(&limits, "a", limit, later),
+            Verdict::Disconnect
+        );
+
+        // Violations are forgotten once the window has passed.
+        let much_later = later + Duration::from_secs(limits.violation_window_secs + 1);
+        assert_eq!(
+            limiter.check(&limits, "a", limit, much_later),
+            Verdict::Allow
+        );
+        assert_eq!(
+            limiter.check(&limits, "a", limit, much_later),
+            Verdict::Allow
+        );
+        assert_eq!(
+            limiter.check(&limits, "a", limit, much_later),
+            Verdict::Warn
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    archive::ArchivedGame,
+    decrypto::{PerTeam, Team},
+    id::UserId,
+};
+
+/// Rating of a player who hasn't finished a game yet.
+pub const INITIAL_RATING: f64 = 1500.0;
+
+/// Rating change for a completely unexpected result.
+/// Players in their first games move faster, so they find their level sooner.
+const K_FACTOR: f64 = 24.0;
+const PROVISIONAL_K_FACTOR: f64 = 48.0;
+const PROVISIONAL_GAMES: usize = 10;
+
+/// Elo rating of a player.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Rating {
+    pub user_id: UserId,
+    /// Nickname in the most recent rated game.
+    pub nick: String,
+    pub rating: f64,
+    pub games: usize,
+}
+
+/// Elo ratings, where each team plays as a single player with the average rating of its members.
+/// Every member then gets the rating change of the team, scaled by their own K-factor.
+#[derive(Debug, Default)]
+pub struct Ratings {
+    players: HashMap<UserId, Rating>,
+}
+
+impl Ratings {
+    pub fn rating(&self, user_id: UserId) -> f64 {
+        self.players
+            .get(&user_id)
+            .map_or(INITIAL_RATING, |r| r.rating)
+    }
+
+    /// Updates the ratings with a finished game. Draws count as half a win for both teams.
+    /// Bots are left out, and games where a team has no human players are not rated.
+    pub fn add_game(&mut self, game: &ArchivedGame) {
+        let teams = PerTeam::from_fn(|team| {
+            game.players
+                .iter()
+                .filter(|p| p.team == Some(team) && !p.is_bot)
+                .collect::<Vec<_>>()
+        });
+        if teams.either(|players| players.is_empty()) {
+            return;
+        }
+
+        let average = teams.as_ref().map(|players| {
+            players.iter().map(|p| self.rating(p.id)).sum::<f64>() / players.len() as f64
+        });
+
+        for team in Team::ORDER {
+            let expected = expected_score(average[team], average[team.other()]);
+            let actual = match game.winner {
+                Some(winner) if winner == team => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+
+            for player in &teams[team] {
+                let rating = self.players.entry(player.id).or_insert_with(|| Rating {
+                    user_id: player.id,
+                    nick: String::new(),
+                    rating: INITIAL_RATING,
+                    games: 0,
+                });
+                let k = if rating.games < PROVISIONAL_GAMES {
+                    PROVISIONAL_K_FACTOR
+                } else {
+                    K_FACTOR
+                };
+                rating.rating += k * (actual - expected);
+                rating.games += 1;
+                rating.nick = player.nick.clone();
+            }
+        }
+    }
+
+    /// Highest rated players first.
+    pub fn leaderboard(&self, limit: usize) -> Vec<&Rating> {
+        let mut ratings: Vec<_> = self.players.values().collect();
+        ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        ratings.truncate(limit);
+        ratings
+    }
+
+    /// Split of the players into two teams with close average ratings.
+    /// Team sizes differ by at most one. Returns `None` for fewer than two players.
+    ///
+    /// Players are dealt strongest first to the weaker team, then pairs are swapped between
+    /// the teams while that brings the averages closer, which is fast for any lobby size.
+    pub fn balanced_teams(&self, players: &[UserId]) -> Option<PerTeam<Vec<UserId>>> {
+        if players.len() < 2 {
+            return None;
+        }
+
+        let mut players: Vec<_> = players.iter().map(|id| (*id, self.rating(*id))).collect();
+        players.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let max_size = players.len().div_ceil(2);
+        let mut teams = [Vec::new(), Vec::new()];
+        for player in players {
+            let total = |team: &Vec<(UserId, f64)>| team.iter().map(|p| p.1).sum::<f64>();
+            let weaker = usize::from(total(&teams[1]) < total(&teams[0]));
+            let team = if teams[weaker].len() < max_size {
+                weaker
+            } else {
+                1 - weaker
+            };
+            teams[team].push(player);
+        }
+
+        let [first, second] = &mut teams;
+        let sizes = [first.len() as f64, second.len() as f64];
+        let total = |team: &[(UserId, f64)]| team.iter().map(|p| p.1).sum::<f64>();
+        let mut totals = [total(first), total(second)];
+        let diff = |totals: [f64; 2]| (totals[0] / sizes[0] - totals[1] / sizes[1]).abs();
+        // Every swap brings the averages strictly closer, so this ends. The limit is a safeguard.
+        for _ in 0..first.len() * second.len() {
+            let current = diff(totals);
+            let mut best: Option<(f64, usize, usize)> = None;
+            for (i, a) in first.iter().enumerate() {
+                for (j, b) in second.iter().enumerate() {
+                    let new = diff([totals[0] - a.1 + b.1, totals[1] - b.1 + a.1]);
+                    if new < current - f64::EPSILON && best.is_none_or(|(best, _, _)| new < best) {
+                        best = Some((new, i, j));
+                    }
+                }
+            }
+            let Some((_, i, j)) = best else {
+                break;
+            };
+            let change = second[j].1 - first[i].1;
+            totals = [totals[0] + change, totals[1] - change];
+            std::mem::swap(&mut first[i], &mut second[j]);
+        }
+
+        Some(PerTeam::from(
+            teams.map(|team| team.into_iter().map(|(id, _)| id).collect()),
+        ))
+    }
+}
+
+/// Probability of winning against the other rating, counting draws as half a win.
+fn expected_score(rating: f64, other: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((other - rating) / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decrypto::record::RecordPlayer,
+        id::{GameId, RecordId},
+    };
+
+    fn game(white: &[UserId], black: &[UserId], winner: Option<Team>) -> ArchivedGame {
+        let player = |id: &UserId, team| RecordPlayer {
+            id: *id,
+            nick: String::new(),
+            team: Some(team),
+            is_bot: false,
+        };
+        ArchivedGame {
+            id: RecordId::new(),
+            game_id: GameId::new(),
+            finished_at: 0,
+            players: white
+                .iter()
+                .map(|id| player(id, Team::WHITE))
+                .chain(black.iter().map(|id| player(id, Team::BLACK)))
+                .collect(),
+            winner,
+        }
+    }
+
+    #[test]
+    fn winners_gain_what_losers_lose() {
+        let (a, b, c, d) = (UserId::new(), UserId::new(), UserId::new(), UserId::new());
+        let mut ratings = Ratings::default();
+        ratings.add_game(&game(&[a, b], &[c, d], Some(Team::WHITE)));
+
+        assert!(ratings.rating(a) > INITIAL_RATING);
+        assert_eq!(ratings.rating(a), ratings.rating(b));
+        assert_eq!(
+            ratings.rating(a) - INITIAL_RATING,
+            INITIAL_RATING - ratings.rating(c)
+        );
+        assert_eq!(ratings.leaderboard(1)[0].rating, ratings.rating(a));
+    }
+
+    #[test]
+    fn draws_move_ratings_towards_each_other() {
+        let (a, b, c, d) = (UserId::new(), UserId::new(), UserId::new(), UserId::new());
+        let mut ratings = Ratings::default();
+        ratings.add_game(&game(&[a], &[b], Some(Team::WHITE)));
+        ratings.add_game(&game(&[c], &[d], None));
+        assert_eq!(ratings.rating(c), INITIAL_RATING);
+
+        let before = ratings.rating(a);
+        ratings.add_game(&game(&[a], &[b], None));
+        assert!(ratings.rating(a) < before);
+        assert!(ratings.rating(b) > INITIAL_RATING - (before - INITIAL_RATING));
+    }
+
+    #[test]
+    fn bots_are_not_rated() {
+        let (a, b, bot) = (UserId::new(), UserId::new(), UserId::new());
+        let mut with_bot = game(&[a, bot], &[b], Some(Team::WHITE));
+        with_bot.players[1].is_bot = true;
+        let mut ratings = Ratings::default();
+        ratings.add_game(&with_bot);
+
+        let mut without_bot = Ratings::default();
+        without_bot.add_game(&game(&[a], &[b], Some(Team::WHITE)));
+        assert_eq!(ratings.rating(a), without_bot.rating(a));
+        assert_eq!(ratings.rating(b), without_bot.rating(b));
+        assert!(ratings.leaderboard(10).iter().all(|r| r.user_id != bot));
+
+        // Against bots only, there is no one to be rated against.
+        let mut against_bot = game(&[a], &[bot], Some(Team::WHITE));
+        against_bot.players[1].is_bot = true;
+        let before = ratings.rating(a);
+        ratings.add_game(&against_bot);
+        assert_eq!(ratings.rating(a), before);
+    }
+
+    #[test]
+    fn teams_are_balanced() {
+        let players: Vec<_> = (0..4).map(|_| UserId::new()).collect();
+        let mut ratings = Ratings::default();
+        // Make the first two players strong, so they should end up in different teams.
+        for _ in 0..5 {
+            ratings.add_game(&game(&players[0..2], &players[2..4], Some(Team::WHITE)));
+        }
+
+        let teams = ratings.balanced_teams(&players).unwrap();
+        assert_eq!(teams[Team::WHITE].len(), 2);
+        assert_ne!(
+            teams[Team::WHITE].contains(&players[0]),
+            teams[Team::WHITE].contains(&players[1])
+        );
+    }
+
+    #[test]
+    fn big_lobbies_are_balanced() {
+        let players: Vec<_> = (0..41).map(|_| UserId::new()).collect();
+        let mut ratings = Ratings::default();
+        for _ in 0..3 {
+            ratings.add_game(&game(&players[0..10], &players[10..20], Some(Team::WHITE)));
+        }
+
+        let teams = ratings.balanced_teams(&players).unwrap();
+        assert_eq!(teams[Team::WHITE].len() + teams[Team::BLACK].len(), 41);
+        assert!(teams[Team::WHITE].len().abs_diff(teams[Team::BLACK].len()) <= 1);
+        let average = |team: Team| {
+            teams[team].iter().map(|p| ratings.rating(*p)).sum::<f64>() / teams[team].len() as f64
+        };
+        assert!((average(Team::WHITE) - average(Team::BLACK)).abs() < 1.0);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    decrypto::{Team, record::GameRecord},
+    id::UserId,
+};
+
+/// Successful attempts out of all attempts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Tally {
+    pub successes: usize,
+    pub attempts: usize,
+}
+
+impl Tally {
+    fn add(&mut self, success: bool) {
+        self.attempts += 1;
+        self.successes += success as usize;
+    }
+}
+
+/// Statistics of a player over all archived games.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PlayerStats {
+    pub games_played: usize,
+    pub games_won: usize,
+    /// Games that ended in a draw after the tiebreaker.
+    pub games_drawn: usize,
+    /// Deciphers of the player's team while they were guessing.
+    pub decipher_as_decryptor: Tally,
+    /// Deciphers of the player's team while they were the encryptor.
+    pub decipher_as_encryptor: Tally,
+    /// Intercepts attempted by the player's team.
+    pub intercepts: Tally,
+    /// Average time the player took to give clues, in milliseconds.
+    pub average_encrypt_millis: Option<u64>,
+    /// Rounds where the player was the encryptor and their team failed to decipher.
+    pub miscommunications_caused: usize,
+    #[serde(skip)]
+    encrypt_millis_total: u64,
+    #[serde(skip)]
+    encrypt_count: u64,
+}
+
+impl PlayerStats {
+    fn add_game(&mut self, record: &GameRecord, user_id: UserId, team: Team) {
+        self.games_played += 1;
+        match record.winner {
+            Some(winner) if winner == team => self.games_won += 1,
+            Some(_) => {}
+            None => self.games_drawn += 1,
+        }
+
+        for round in &record.rounds {
+            let own = &round[team];
+            if let Some(success) = own.score.intercept {
+                self.intercepts.add(success);
+            }
+
+            if own.non_computed.encryptor != user_id {
+                self.decipher_as_decryptor.add(own.score.decipher);
+                continue;
+            }
+
+            self.decipher_as_encryptor.add(own.score.decipher);
+            if !own.score.decipher {
+                self.miscommunications_caused += 1;
+            }
+            if let Some(millis) = own.non_computed.encrypt_millis {
+                self.encrypt_millis_total += millis;
+                self.encrypt_count += 1;
+                self.average_encrypt_millis = Some(self.encrypt_millis_total / self.encrypt_count);
+            }
+        }
+    }
+}
+
+/// Adds a finished game to the stats of every human who played in a team.
+pub fn add_game(stats: &mut HashMap<UserId, PlayerStats>, record: &GameRecord) {
+    for player in record.players.iter().filter(|p| !p.is_bot) {
+        if let Some(team) = player.team {
+            stats
+                .entry(player.id)
+                .or_default()
+                .add_game(record, player.id, team);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decrypto::{
+            Code, PerTeam, RoundPerTeam, RoundResult, TimedOut, record::RecordPlayer,
+            settings::GameSettings, wordlist::Language,
+        },
+        id::{GameId, RecordId},
+        message::CompletedRoundPerTeam,
+    };
+
+    fn round(encryptor: UserId, decipher: bool, millis: u64) -> CompletedRoundPerTeam {
+        CompletedRoundPerTeam {
+            non_computed: RoundPerTeam {
+                encryptor,
+                code: Code(vec![0, 1, 2]),
+                clues: None,
+                decipher: None,
+                intercept: None,
+                timed_out: TimedOut::default(),
+                started_at: None,
+                encrypt_millis: Some(millis),
+            },
+            score: RoundResult {
+                intercept: Some(!decipher),
+                decipher,
+            },
+        }
+    }
+
+    #[test]
+    fn stats_are_counted_per_role() {
+        let (alice, bob, carol) = (UserId::new(), UserId::new(), UserId::new());
+        let player = |id, team| RecordPlayer {
+            id,
+            nick: String::new(),
+            team: Some(team),
+            is_bot: false,
+        };
+        let record = GameRecord {
+            id: RecordId::new(),
+            game_id: GameId::new(),
+            finished_at: 0,
+            settings: GameSettings::default(),
+            language: Language::default(),
+            players: vec![
+                player(alice, Team::WHITE),
+                player(bob, Team::WHITE),
+                player(carol, Team::BLACK),
+            ],
+            winner: Some(Team::WHITE),
+            keywords: PerTeam::splat(Vec::new()),
+            rounds: vec![
+                PerTeam([round(alice, true, 1000), round(carol, false, 500)]),
+                PerTeam([round(bob, false, 3000), round(carol, true, 1500)]),
+                PerTeam([round(alice, true, 2000), round(carol, true, 1000)]),
+            ],
+            tiebreaker: None,
+            drawings: HashMap::new(),
+        };
+
+        let mut stats = HashMap::new();
+        add_game(&mut stats, &record);
+
+        let alice = &stats[&alice];
+        assert_eq!((alice.games_played, alice.games_won), (1, 1));
+        assert_eq!(alice.decipher_as_encryptor.successes, 2);
+        assert_eq!(alice.decipher_as_decryptor.attempts, 1);
+        assert_eq!(alice.average_encrypt_millis, Some(1500));
+        assert_eq!(alice.intercepts.successes, 1);
+
+        assert_eq!(stats[&bob].miscommunications_caused, 1);
+        assert_eq!(stats[&carol].games_won, 0);
+        assert_eq!(stats[&carol].miscommunications_caused, 1);
+    }
+}
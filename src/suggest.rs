@@ -0,0 +1,144 @@
+use crate::{
+    bot::model::AssociationModel,
+    decrypto::{Code, RoundPerTeam},
+    message::{Clue, CodeSuggestion},
+};
+
+/// Score for matching a clue to a keyword slot nothing is known about yet.
+pub const UNSEEN_SLOT_SCORE: f32 = 0.2;
+/// Most codes ranked at once, which covers 4 clues for 10 keywords.
+const MAX_CODES: usize = 5040;
+/// Spread of the probabilities. Lower values make the best codes more certain.
+const TEMPERATURE: f64 = 0.1;
+
+/// Text clues a team has given for each of its keywords in the given rounds.
+pub fn clue_history<'a>(
+    rounds: impl IntoIterator<Item = &'a RoundPerTeam>,
+    keyword_count: usize,
+) -> Vec<Vec<String>> {
+    let mut history = vec![Vec::new(); keyword_count];
+    for round in rounds {
+        let Some(clues) = &round.clues else {
+            continue;
+        };
+        for (clue, keyword) in clues.iter().zip(&round.code.0) {
+            if let (Clue::Text(text), Some(clues)) = (clue, history.get_mut(*keyword)) {
+                clues.push(text.to_lowercase());
+            }
+        }
+    }
+    history
+}
+
+/// How well each clue fits each keyword slot, as `scores[clue][slot]`.
+/// `slots` has the words known for each slot: earlier clues, and the keyword itself if known.
+/// A clue scores its best similarity to any of them.
+pub fn slot_scores(
+    model: &AssociationModel,
+    clues: &[Clue],
+    slots: &[Vec<String>],
+) -> Vec<Vec<f32>> {
+    clues
+        .iter()
+        .map(|clue| {
+            slots
+                .iter()
+                .map(|words| {
+                    let Clue::Text(clue) = clue else {
+                        return UNSEEN_SLOT_SCORE;
+                    };
+                    words
+                        .iter()
+                        .filter_map(|word| model.similarity(clue, word))
+                        .reduce(f32::max)
+                        .unwrap_or(UNSEEN_SLOT_SCORE)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The `count` most likely codes, best first.
+/// A code is as likely as the summed scores of its clues in their slots, through a softmax.
+pub fn rank_codes(scores: &[Vec<f32>], count: usize) -> Result<Vec<CodeSuggestion>, String> {
+    let clue_count = scores.len();
+    let slot_count = scores.first().map_or(0, |row| row.len());
+    let code_count = (0..clue_count)
+        .try_fold(1usize, |total, i| {
+            total.checked_mul(slot_count.saturating_sub(i))
+        })
+        .filter(|total| *total <= MAX_CODES)
+        .ok_or("Too many possible codes to rank")?;
+
+    let mut codes = Vec::with_capacity(code_count);
+    let mut code = Vec::with_capacity(clue_count);
+    permutations(slot_count, clue_count, &mut code, &mut codes);
+
+    let logits: Vec<f64> = codes
+        .iter()
+        .map(|code| {
+            let score: f32 = code
+                .iter()
+                .enumerate()
+                .map(|(clue, slot)| scores[clue][*slot])
+                .sum();
+            score as f64 / TEMPERATURE
+        })
+        .collect();
+    let max = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = logits.iter().map(|logit| (logit - max).exp()).collect();
+    let total: f64 = weights.iter().sum();
+
+    let mut suggestions: Vec<_> = codes
+        .into_iter()
+        .zip(weights)
+        .map(|(code, weight)| CodeSuggestion {
+            code: Code(code),
+            probability: weight / total,
+        })
+        .collect();
+    suggestions.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+    suggestions.truncate(count);
+    Ok(suggestions)
+}
+
+/// All ordered picks of `length` different slots.
+fn permutations(slots: usize, length: usize, code: &mut Vec<usize>, codes: &mut Vec<Vec<usize>>) {
+    if code.len() == length {
+        codes.push(code.clone());
+        return;
+    }
+    for slot in 0..slots {
+        if !code.contains(&slot) {
+            code.push(slot);
+            permutations(slots, length, code, codes);
+            code.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_ranked_by_clue_fit() {
+        let scores = vec![
+            vec![0.9, 0.1, 0.1, 0.2],
+            vec![0.1, 0.1, 0.8, 0.2],
+            vec![0.2, 0.7, 0.1, 0.2],
+        ];
+        let suggestions = rank_codes(&scores, 3).unwrap();
+        assert_eq!(suggestions.len(), 3);
+        assert_eq!(suggestions[0].code, Code(vec![0, 2, 1]));
+        assert!(suggestions[0].probability > suggestions[1].probability);
+
+        let all = rank_codes(&scores, usize::MAX).unwrap();
+        assert_eq!(all.len(), 24);
+        let total: f64 = all.iter().map(|s| s.probability).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        let scores = vec![vec![0.0; 20]; 4];
+        assert!(rank_codes(&scores, 1).is_err());
+    }
+}
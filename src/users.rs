@@ -0,0 +1,35 @@
+use std::{io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::id::{UserId, UserSecret};
+
+/// Identity of a user, persisted so that sessions and stats survive restarts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StoredUser {
+    pub id: UserId,
+    pub secret: UserSecret,
+    pub nick: Option<String>,
+    /// Banned users cannot log in.
+    #[serde(default)]
+    pub banned: bool,
+}
+
+/// Reads the stored users, or nothing if the file doesn't exist yet.
+pub fn load_users(path: &Path) -> io::Result<Vec<StoredUser>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(io::Error::other),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn save_users(path: &Path, users: &[StoredUser]) -> io::Result<()> {
+    let json = serde_json::to_vec(users).expect("Should serialize");
+
+    // Write to a temporary file first, so a crash never leaves a partial file behind.
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, path).await
+}
@@ -1,4 +1,4 @@
-import { css } from 'https://unpkg.com/lit?module';
+import { css, html } from 'https://unpkg.com/lit?module';
 
 const inputActionCSS = css`
 .input-action {
@@ -12,4 +12,29 @@ const inputActionCSS = css`
 }
 `;
 
-export { inputActionCSS };
\ No newline at end of file
+// Button asking the server for likely codes, and the answer for the current round if there is one.
+const renderSuggestions = (element, intercept) => {
+    if (!element.game.settings.suggestions) return null;
+    const suggestions = element.suggestions;
+    const current = suggestions
+        && suggestions.intercept === intercept
+        && suggestions.round === element.game.completed_rounds.length;
+    return html`
+        <div class="suggestions">
+            <input
+                type="button"
+                value="Suggest codes"
+                @click=${() => element.dispatchEvent(new CustomEvent('send-cmd', {
+                    detail: { request_suggestions: { intercept } },
+                    bubbles: true,
+                    composed: true,
+                }))}
+            />
+            ${current ? html`<ul>${suggestions.suggestions.map((s) => html`
+                <li>${s.code.map((i) => i + 1).join('-')} (${Math.round(s.probability * 100)}%)</li>
+            `)}</ul>` : null}
+        </div>
+    `;
+};
+
+export { inputActionCSS, renderSuggestions };
\ No newline at end of file
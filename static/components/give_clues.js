@@ -43,6 +43,8 @@ class ClueGiverView extends LitElement {
                 data.push({ text: this.clues[i].text });
             } else if (this.clues[i]?.drawing) {
                 data.push({ drawing: this.clues[i].drawing });
+            } else if (this.clues[i]?.strokes) {
+                data.push({ strokes: this.clues[i].strokes });
             } else {
                 console.error(`Clue input ${i} is empty, skipping`);
             }
@@ -61,9 +63,9 @@ class ClueGiverView extends LitElement {
         const clue = this.clues[clueIndex];
         let input = [];
 
-        if (clue?.drawing) {
+        if (clue?.drawing || clue?.strokes) {
             input.push(html`
-                ${semantic.clueDrawing(this.state, clue.drawing)}
+                ${semantic.clue(this.state, clue)}
                 <input
                     type="button"
                     value="Remove drawing"
@@ -114,7 +116,7 @@ class ClueGiverView extends LitElement {
     render() {
         const code = this.game.inputs.encrypt.code;
         const deadline = this.game.inputs.encrypt.deadline;
-        const disabled = this.clues.length !== code.length || this.clues.some((c) => !c?.text && !c?.drawing);
+        const disabled = this.clues.length !== code.length || this.clues.some((c) => !c?.text && !c?.drawing && !c?.strokes);
 
         return html`
         <div class="input-action">
@@ -139,7 +141,7 @@ class ClueGiverView extends LitElement {
                 ? html`<paint-overlay
                     .gameId=${this.game.id}
                     @drawing-saved=${(e) => {
-                        this.clues[this.paintView] = { drawing: e.detail };
+                        this.clues[this.paintView] = e.detail;
                     }}
                 >
                     <div>
@@ -1,11 +1,12 @@
 import { LitElement, html } from 'https://unpkg.com/lit?module';
-import { inputActionCSS } from './common.js';
+import { inputActionCSS, renderSuggestions } from './common.js';
 import semantic from '../semantic.js';
 
 class InterceptView extends LitElement {
     static properties = {
         game: { type: Object },
         user_info: { type: Object },
+        suggestions: { type: Object },
         value: { type: Object },
     };
 
@@ -77,6 +78,7 @@ class InterceptView extends LitElement {
                     @input=${(e) => this.value = e.target.value}
                     @keypress=${this.handleKeyPress.bind(this)}
                 >
+                ${renderSuggestions(this, true)}
             </div>
         `;
     }
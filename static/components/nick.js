@@ -8,6 +8,7 @@ class NicknameInput extends LitElement {
     constructor() {
         super();
         this.value = '';
+        this.pairingCode = '';
     }
 
     handleInput(e) {
@@ -24,6 +25,17 @@ class NicknameInput extends LitElement {
         }
     }
 
+    handlePairingKeyPress(e) {
+        this.pairingCode = e.target.value;
+        if (e.key === 'Enter' && this.pairingCode.trim()) {
+            this.dispatchEvent(new CustomEvent('pair', {
+                detail: this.pairingCode.trim(),
+                bubbles: true,
+                composed: true,
+            }));
+        }
+    }
+
     firstUpdated() {
         this.shadowRoot.getElementById('nick-input')?.focus();
     }
@@ -40,6 +52,13 @@ class NicknameInput extends LitElement {
                     @input=${this.handleInput}
                     @keypress=${this.handleKeyPress}
                 />
+                <p>Or, to continue as yourself from another device, enter the pairing code shown there:</p>
+                <input
+                    id="pairing-code-input"
+                    type="text"
+                    placeholder="Pairing code"
+                    @keyup=${this.handlePairingKeyPress}
+                />
             </div>
         `;
     }
@@ -198,22 +198,54 @@ class PaintOverlay extends LitElement {
         }
     `;
 
+    // Drawings without bucket fills are uploaded as strokes, which the server can render at any size.
+    strokes() {
+        if (this.drawHistory.some(h => h.type !== 'pen' && h.type !== 'line')) {
+            return null;
+        }
+        const clamp = (v, max) => Math.min(Math.max(v, 0), max);
+        return {
+            width,
+            height,
+            strokes: this.drawHistory.map(h => ({
+                color: h.color,
+                width: Number(h.size),
+                points: h.points.map(p => [clamp(p.x, width), clamp(p.y, height), p.t]),
+            })),
+        };
+    }
+
+    // `kind` is the clue type the drawing is given as, `drawing` or `strokes`.
+    async uploadDrawing(body, contentType, kind) {
+        let r = await fetch('/drawing/' + this.gameId, {
+            method: 'POST',
+            body,
+            headers: {
+                'Content-Type': contentType,
+                'Authorization': 'Bearer ' + localStorage.getItem('secret'),
+            },
+        });
+        if (!r.ok) {
+            console.error('Failed to upload drawing: ' + await r.text());
+            return;
+        }
+        let drawingId = await r.text();
+        this.dispatchEvent(new CustomEvent('drawing-saved', {
+            detail: { [kind]: drawingId },
+            bubbles: true,
+            composed: true,
+        }));
+    }
+
     saveDrawing() {
+        let strokes = this.strokes();
+        if (strokes) {
+            this.uploadDrawing(JSON.stringify(strokes), 'application/json', 'strokes');
+            return;
+        }
         this.canvas.toBlob(async (img) => {
             if (img) {
-                let r = await fetch('/drawing/' + this.gameId, {
-                    method: 'POST',
-                    body: img,
-                    headers: {
-                        'Content-Type': 'image/png',
-                    },
-                });
-                let drawingId = await r.text();
-                this.dispatchEvent(new CustomEvent('drawing-saved', {
-                    detail: drawingId,
-                    bubbles: true,
-                    composed: true,
-                }));
+                await this.uploadDrawing(img, 'image/png', 'drawing');
             } else {
                 console.error('Failed to create image blob');
             }
@@ -289,12 +321,13 @@ class PaintOverlay extends LitElement {
                         @click=${e => {
                             this.drawHistory = [];
                             this.redoStack = [];
+                            this.startedAt = undefined;
                             this.redraw();
                             this.requestUpdate();
                         }}
                     />
                     <input type="button" class="tool save" value="Save"
-                        @click=${e => saveDrawing()}}
+                        @click=${e => this.saveDrawing()}
                     />
                 </div>
             </div>
@@ -323,7 +356,11 @@ class PaintOverlay extends LitElement {
         const x = xCss * scaleX;
         const y = yCss * scaleY;
 
-        return { x, y };
+        // Milliseconds since the first stroke, for replaying the drawing.
+        this.startedAt ??= e.timeStamp;
+        const t = Math.round(e.timeStamp - this.startedAt);
+
+        return { x, y, t };
     }
 
 
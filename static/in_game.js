@@ -17,7 +17,7 @@ const renderKeywords = (game) => {
 };
 
 
-const renderAction = (game, user_info) => {
+const renderAction = (game, user_info, suggestions) => {
     let myTeam = game.players.find((p) => p.id === user_info.id).team;
 
     if ('encrypt' in game.inputs) {
@@ -31,8 +31,8 @@ const renderAction = (game, user_info) => {
         let decipher = game.inputs.guess.decipher;
         let deadline = game.inputs.guess.deadline;
         return html`${[
-            decipher ? html`<decipher-view .game=${game} .user_info=${user_info}></decipher-view>` : null,
-            intercept ? html`<intercept-view .game=${game} .user_info=${user_info}></intercept-view>` : null,
+            decipher ? html`<decipher-view .game=${game} .user_info=${user_info} .suggestions=${suggestions}></decipher-view>` : null,
+            intercept ? html`<intercept-view .game=${game} .user_info=${user_info} .suggestions=${suggestions}></intercept-view>` : null,
             deadline ? html`<deadline-display .game=${game} .deadline=${deadline}></deadline-display>` : null,
         ]}`;
     } else if ('waiting_for_encryptors' in game.inputs || 'waiting_for_guessers' in game.inputs) {
@@ -220,6 +220,24 @@ export default function viewInGame(state) {
         <div id="in_game">
             <div class="input-action game-over">
                 <h1>Game Over: ${winner === null ? 'draw' : (winner === myTeam ? 'you won!' : 'you lost!')}</h1>
+                <input
+                    type="button"
+                    id="return-to-lobby"
+                    value="Back to lobby"
+                    @click=${() => {
+                        state.dispatchEvent(new CustomEvent('send-cmd', {
+                            detail: { return_to_lobby: null },
+                            bubbles: true,
+                            composed: true,
+                        }));
+                    }}
+                />
+                ${state.game.record_id ? html`<div class="export">
+                    Export:
+                    <a href="/games/${state.game.record_id}/export?format=html" target="_blank">HTML</a>
+                    <a href="/games/${state.game.record_id}/export?format=markdown" target="_blank">Markdown</a>
+                    <a href="/games/${state.game.record_id}/export?format=json" target="_blank">JSON</a>
+                </div>` : null}
                 <h2>Keywords for your team were:</h2>
                 <div class="row keywords">
                 ${state.game.keywords[+myTeam].map((keyword, index) =>
@@ -243,7 +261,7 @@ export default function viewInGame(state) {
     return html`
     <div id="in_game">
         ${renderKeywords(state.game)}
-        ${renderAction(state.game, state.user_info)}
+        ${renderAction(state.game, state.user_info, state.suggestions)}
         <div class="spacer"></div>
         ${renderInterceptionMatrix(state, !myTeam)}
         ${renderRoundHistory(state)}
@@ -1,7 +1,28 @@
 import { html } from 'https://unpkg.com/lit?module';
 import semantic from './semantic.js';
 
-const kickButton = (state, playerId) => {
+const botsAvailable = (state) => state.game.association_bots_available || state.game.llm_bots_available;
+
+const addBotButton = (state, team) => {
+    if (!botsAvailable(state)) {
+        return null;
+    }
+    return html`
+        <input
+            type="button"
+            value="Add bot"
+            @click=${() => {
+                state.dispatchEvent(new CustomEvent('send-cmd', {
+                    detail: { add_bot: { team, ...JSON.parse(document.getElementById('bot-kind').value) } },
+                    bubbles: true,
+                    composed: true,
+                }));
+            }}
+        />
+    `;
+};
+
+const kickButton = (state, playerId, label = 'Kick') => {
     return html`
         <button class="kick-button" @click=${() => {
             state.dispatchEvent(new CustomEvent('send-cmd', {
@@ -10,7 +31,7 @@ const kickButton = (state, playerId) => {
                 composed: true,
             }));
         }}>
-            Kick
+            ${label}
         </button>
     `;
 };
@@ -19,11 +40,69 @@ const renderPlayer = (state, player) => {
     return html`
         <div class="player">
             ${semantic.player(state, player.id)}
+            <span class="rating">(${Math.round(player.rating)})</span>
+            ${player.is_bot ? kickButton(state, player.id, 'Remove') : null}
             ${player.connected ? null : ['(disconnected', kickButton(state, player.id), ')']}
         </div>
     `;
 };
 
+const changeSettings = (state, f) => {
+    let settings = JSON.parse(JSON.stringify(state.game.settings));
+    f(settings);
+    state.dispatchEvent(new CustomEvent('send-cmd', {
+        detail: { change_settings: settings },
+        bubbles: true,
+        composed: true,
+    }));
+};
+
+const changeWordlists = (state, f) => changeSettings(state, (settings) => {
+    settings.wordlists = f(settings.wordlists);
+});
+
+const wordlistRow = (state, wl) => {
+    const choice = state.game.settings.wordlists.find((c) => c.id === wl.id);
+    const update = (changes) =>
+        changeWordlists(state, (wordlists) => wordlists.map((c) => c.id === wl.id ? { ...c, ...changes } : c));
+    return html`
+        <tr title="${wl.description ?? ''}">
+            <td>
+                <input
+                    type="checkbox"
+                    ?checked=${choice !== undefined}
+                    @change=${(e) =>
+        changeWordlists(state, (wordlists) =>
+            e.target.checked
+                ? [...wordlists, { id: wl.id, weight: 1, quota: null }]
+                : wordlists.filter((c) => c.id !== wl.id)
+        )}
+                />
+            </td>
+            <td>${wl.name} (${wl.word_count} keywords${wl.difficulty ? ', ' + wl.difficulty : ''})</td>
+            <td>
+                <input
+                    type="number"
+                    min="0"
+                    .value=${choice?.weight ?? 1}
+                    ?disabled=${choice === undefined || choice.quota !== null}
+                    @change=${(e) => update({ weight: parseInt(e.target.value) || 0 })}
+                />
+            </td>
+            <td>
+                <input
+                    type="number"
+                    min="0"
+                    placeholder="none"
+                    .value=${choice?.quota ?? ''}
+                    ?disabled=${choice === undefined}
+                    @change=${(e) => update({ quota: e.target.value === '' ? null : parseInt(e.target.value) })}
+                />
+            </td>
+        </tr>
+    `;
+};
+
 export default function viewLobby(state) {
     return html`
     <div id="lobby">
@@ -59,6 +138,19 @@ export default function viewLobby(state) {
             ${state.game.reason_not_startable ? html`(${state.game.reason_not_startable})` : ''}
         </p>
         <h2>Teams</h2>
+        ${botsAvailable(state) ? html`
+            <p>
+                Bot:
+                <select id="bot-kind">
+                    ${state.game.association_bots_available ? html`
+                        <option value='{"kind": "association", "difficulty": "easy"}'>Easy</option>
+                        <option value='{"kind": "association", "difficulty": "normal"}' selected>Normal</option>
+                        <option value='{"kind": "association", "difficulty": "hard"}'>Hard</option>
+                    ` : ''}
+                    ${state.game.llm_bots_available ? html`<option value='{"kind": "llm"}'>LLM</option>` : ''}
+                </select>
+            </p>
+        ` : ''}
         <div id="lobby-teams" class="row wrap">
             <div>
                 <h3>No team selected</h3>
@@ -79,6 +171,7 @@ export default function viewLobby(state) {
                             }));
                         }}
                     />
+                    ${addBotButton(state, false)}
                 </h3>
                 ${
         state.game.players.filter((p) => p.is_in_game && p.team === false).map(
@@ -101,28 +194,82 @@ export default function viewLobby(state) {
                             }));
                         }}
                     />
+                    ${addBotButton(state, true)}
                 </h3>
                 ${state.game.players.filter((p) => p.is_in_game && p.team === true).map((p) => renderPlayer(state, p))}
             </div>
         </div>
+        ${state.game.suggested_teams ? html`
+            <p id="suggested-teams">
+                Suggested fair teams:
+                Team 1: ${state.game.suggested_teams[0].map((id) => semantic.player(state, id))},
+                Team 2: ${state.game.suggested_teams[1].map((id) => semantic.player(state, id))}
+            </p>
+        ` : ''}
         <h2>Settings</h2>
-        <h3>Wordlist</h3>
-        <select id="wordlist-select" @change=${(e) => {
-        let settings = JSON.parse(JSON.stringify(state.game.settings));
-        settings.wordlist = e.target.value;
+        ${state.game.association_bots_available ? html`
+            <p>
+                <label>
+                    <input
+                        type="checkbox"
+                        id="suggestions"
+                        ?checked=${state.game.settings.suggestions}
+                        @change=${(e) => changeSettings(state, (settings) => {
+                            settings.suggestions = e.target.checked;
+                        })}
+                    />
+                    Allow asking for code suggestions
+                </label>
+            </p>
+        ` : ''}
+        <h3>Wordlists</h3>
+        <table id="wordlist-select">
+            <thead>
+                <tr><th>Use</th><th>Wordlist</th><th>Weight</th><th>Quota</th></tr>
+            </thead>
+            <tbody>
+            ${
+        Object.entries(Object.groupBy(state.wordlists, (wl) => wl.language)).map(([language, wordlists]) =>
+            html`<tr><th colspan="4">${language}</th></tr>${wordlists.map((wl) => wordlistRow(state, wl))}`
+        )
+    }
+            ${state.game.custom_wordlist !== null ? wordlistRow(state, state.game.custom_wordlist) : ''}
+            </tbody>
+        </table>
+        <h3>Keyword history</h3>
+        ${
+        state.game.keyword_history.length === 0 ? html`<p>No keywords used yet.</p>` : html`
+            <details>
+                <summary>${state.game.keyword_history.length} keywords used in earlier games will not be picked again</summary>
+                <p>${state.game.keyword_history.join(', ')}</p>
+            </details>
+            <input
+                type="button"
+                value="Clear history"
+                @click=${() => {
+            state.dispatchEvent(new CustomEvent('send-cmd', {
+                detail: { clear_keyword_history: null },
+                bubbles: true,
+                composed: true,
+            }));
+        }}
+            />
+        `
+    }
+        <h3>Custom wordlist</h3>
+        <p>One keyword per line, optionally starting with <code># language: en</code> style header lines. Enable it above to use it.</p>
+        <textarea id="custom-wordlist" rows="6"></textarea>
+        <input
+            type="button"
+            value="Upload wordlist"
+            @click=${() => {
         state.dispatchEvent(new CustomEvent('send-cmd', {
-            detail: { change_settings: settings },
+            detail: { set_custom_wordlist: document.getElementById('custom-wordlist').value.split('\n') },
             bubbles: true,
             composed: true,
         }));
-    }}>
-            ${
-        state.wordlists.map((wl) =>
-            html`<option value="${wl}" ?selected=${wl === state.game.settings.wordlist}>${wl}</option>`
-        )
-    }
-        </select>
-        TODO: custom wordlists
+    }}
+        />
         <h3>Other settings</h3>
         TODO: proper settings editor
         <pre><code>${JSON.stringify(state.game.settings, null, 2)}</code></pre>
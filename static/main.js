@@ -24,6 +24,9 @@ class AppRoot extends LitElement {
         wordlists: { type: Array },
         error: { type: Object },
         error_expires: { type: Number },
+        suggestions: { type: Object },
+        restarting: { type: Object },
+        pairing_code: { type: Object },
     };
 
     constructor() {
@@ -38,6 +41,10 @@ class AppRoot extends LitElement {
         this.wordlists = [];
         this.error = null;
         this.error_expires = null;
+        this.suggestions = null;
+        this.restarting = null;
+        this.pairing_code = null;
+        this.redeeming = false;
     }
 
     createRenderRoot() {
@@ -60,11 +67,19 @@ class AppRoot extends LitElement {
         this.ws = new WebSocket('/ws');
         this.ws.addEventListener('message', e => this.onMessage(e));
         this.ws.addEventListener('open', () => {
-            this.dispatchEvent(new CustomEvent('send-cmd', {
-                detail: { auth: { secret: localStorage.getItem('secret') || null } },
-                bubbles: true,
-                composed: true,
-            }));
+            if (window.location.hash.startsWith('#pair_')) {
+                // Opened from a pairing link, log in as the user that shared it
+                const code = window.location.hash.slice(6);
+                window.location.hash = '';
+                this.redeeming = true;
+                this.dispatchEvent(new CustomEvent('send-cmd', {
+                    detail: { redeem_pairing_code: code },
+                    bubbles: true,
+                    composed: true,
+                }));
+                return;
+            }
+            this.authenticate();
         });
         this.ws.addEventListener('error', (e) => {
             console.error('WebSocket error:', e);
@@ -74,6 +89,26 @@ class AppRoot extends LitElement {
             document.getElementById('error').classList.add('severity-error');
         });
         this.ws.addEventListener('close', (e) => {
+            if (e.code === 1000 && e.reason) {
+                document.getElementById('error').innerText = e.reason;
+                document.getElementById('error').classList.remove('severity-info');
+                document.getElementById('error').classList.remove('severity-error');
+                document.getElementById('error').classList.add('severity-warning');
+                return;
+            }
+            if (this.restarting) {
+                const downtime = this.restarting.expected_downtime_secs;
+                document.getElementById('error').innerText = downtime == null
+                    ? 'Server is restarting, please reload the page in a moment'
+                    : `Server is restarting, reloading in about ${downtime} seconds`;
+                document.getElementById('error').classList.remove('severity-info');
+                document.getElementById('error').classList.remove('severity-error');
+                document.getElementById('error').classList.add('severity-warning');
+                if (downtime != null) {
+                    setTimeout(() => window.location.reload(), downtime * 1000);
+                }
+                return;
+            }
             document.getElementById('error').innerText = 'Server closed connection unexpectedly ' + e.reason;
             document.getElementById('error').classList.remove('severity-info');
             document.getElementById('error').classList.remove('severity-warning');
@@ -89,10 +124,19 @@ class AppRoot extends LitElement {
         document.getElementById('init-load').remove();
     }
 
+    authenticate() {
+        this.dispatchEvent(new CustomEvent('send-cmd', {
+            detail: { auth: { secret: localStorage.getItem('secret') || null } },
+            bubbles: true,
+            composed: true,
+        }));
+    }
+
     onMessage(event) {
         const msg = JSON.parse(event.data);
         console.log('recv: ' + JSON.stringify(msg));
         if (msg.state) {
+            this.redeeming = false;
             this.user_info = msg.state.user_info;
             this.game = msg.state.game;
             this.requestUpdate();
@@ -104,6 +148,22 @@ class AppRoot extends LitElement {
                 el.scrollTop = el.scrollHeight;
             }
         }
+        if (msg.suggestions) {
+            this.suggestions = msg.suggestions;
+            this.requestUpdate();
+        }
+        if (msg.pairing_code) {
+            this.pairing_code = msg.pairing_code;
+            this.pairing_code.expires_at = Date.now() + msg.pairing_code.expires_in_secs * 1000;
+        }
+        if (msg.error && this.redeeming) {
+            // The pairing link didn't work, continue as before
+            this.redeeming = false;
+            this.authenticate();
+        }
+        if (msg.server_restarting) {
+            this.restarting = msg.server_restarting;
+        }
         if (msg.error) {
             // TODO: proper toast system, and different handling for hard errors
             let id = Math.random().toString(36);
@@ -138,6 +198,13 @@ class AppRoot extends LitElement {
                         composed: true,
                     }));
                     this.override_view = null;
+                }} @pair=${e => {
+                    this.dispatchEvent(new CustomEvent('send-cmd', {
+                        detail: { redeem_pairing_code: e.detail },
+                        bubbles: true,
+                        composed: true,
+                    }));
+                    this.override_view = null;
                 }}></nickname-input>`
             ]}`;
         }
@@ -190,7 +257,7 @@ class AppRoot extends LitElement {
 customElements.define('app-root', AppRoot);
 
 window.onhashchange = () => {
-    if (window.location.hash.startsWith('#join_')) {
+    if (window.location.hash.startsWith('#join_') || window.location.hash.startsWith('#pair_')) {
         window.location.reload();
     }
 };
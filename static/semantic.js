@@ -23,7 +23,7 @@ const startImageView = (e) => {
         container.id = 'image-viewer-overlay';
         container.style = 'pointer-events: none;';
         let img = document.createElement('img');
-        img.src = e.target.src;
+        img.src = e.target.src.split('?')[0];
         img.style = 'pointer-events: none;';
         container.appendChild(img);
         document.body.appendChild(container);
@@ -108,7 +108,18 @@ const clueDrawing = (state, draingID) => {
         x-clue-drawing="${draingID}"
         @mouseenter=${startImageView}
         @mouseleave=${endImageView}
-        src="/drawing/${state.game.id}/${draingID}"
+        src="/drawing/${state.game.id}/${draingID}?height=100"
+    >`;
+};
+
+// Strokes are served as svg, which stays sharp at any size.
+const clueStrokes = (state, drawingID) => {
+    return html`<img
+        class="semantic-clue-drawing"
+        x-clue-drawing="${drawingID}"
+        @mouseenter=${startImageView}
+        @mouseleave=${endImageView}
+        src="/drawing/${state.game.id}/${drawingID}?format=svg&height=100"
     >`;
 };
 
@@ -117,6 +128,8 @@ const clue = (state, clue) => {
         return clueText(state, clue.text);
     } else if (clue.drawing) {
         return clueDrawing(state, clue.drawing);
+    } else if (clue.strokes) {
+        return clueStrokes(state, clue.strokes);
     } else {
         return html`<span>[Unknown clue type]</span>`;
     }
@@ -132,4 +145,5 @@ export default {
     clue,
     clueText,
     clueDrawing,
+    clueStrokes,
 };
@@ -78,6 +78,10 @@ const renderChatMessage = (state, msg) => {
         if (m) {
             return semantic.clueDrawing(state, m[1]);
         }
+        m = tag.match(/^clue:strokes:(.+)$/);
+        if (m) {
+            return semantic.clueStrokes(state, m[1]);
+        }
         if (tag == 'br' || tag == '\n') {
             return html`<br>`;
         }
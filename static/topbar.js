@@ -15,6 +15,36 @@ const resetNick = (state) => {
     state.override_view = 'nick_required';
 };
 
+const requestPairingCode = (state, revokeOthers) => {
+    state.dispatchEvent(new CustomEvent('send-cmd', {
+        detail: { request_pairing_code: { revoke_others: revokeOthers } },
+        bubbles: true,
+        composed: true,
+    }));
+};
+
+const renderPairing = (state) => {
+    const pairing = state.pairing_code;
+    if (!pairing || pairing.expires_at < Date.now()) {
+        return html`<div>
+            <input type="button" value="Add device" title="Play as yourself on another device too"
+                @click=${() => requestPairingCode(state, false)}>
+            <input type="button" value="Move to device" title="Continue on another device, logging out here"
+                @click=${() => requestPairingCode(state, true)}>
+        </div>`;
+    }
+    const code = pairing.code.slice(0, 4) + '-' + pairing.code.slice(4);
+    const link = `${window.location.origin}/#pair_${pairing.code}`;
+    const minutes = Math.ceil((pairing.expires_at - Date.now()) / 60000);
+    return html`<div class="pairing-code">
+        Code <b>${code}</b> or <a href=${link} @click=${(e) => e.preventDefault()}>link</a>,
+        valid for ${minutes} min${pairing.revoke_others ? ', logs out this device' : ''}
+        <input type="button" value="✕" @click=${() => {
+            state.pairing_code = null;
+        }}>
+    </div>`;
+};
+
 export default function topbar(state) {
     const isLocalhost = window.location.hostname === 'localhost' ||
         window.location.hostname === '[::1]' ||
@@ -44,6 +74,14 @@ export default function topbar(state) {
                 _,
             ) => resetNick(state)}></div>`
             : html`<div>Nick not set</div>`
+    }
+        ${state.user_info?.nick ? renderPairing(state) : null}
+        ${
+        state.user_info?.devices?.length > 1
+            ? html`<div class="devices" title=${
+                state.user_info.devices.map((d) => d.current ? `${d.name} (this device)` : d.name).join('\n')
+            }>Connected from ${state.user_info.devices.length} devices</div>`
+            : null
     }
     </div>
     `;